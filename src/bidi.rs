@@ -0,0 +1,83 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+// Whether a grapheme reads right to left, or None for spaces and punctuation
+fn direction(g: &str) -> Option<bool> {
+    let c = g.chars().next()?;
+    let rtl = matches!(
+        c,
+        '\u{0590}'..='\u{08FF}' | '\u{FB1D}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFF}'
+    );
+    if rtl {
+        Some(true)
+    } else if c.is_alphanumeric() {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+// Each paragraph takes its direction from its first letter
+pub fn paragraph_directions(rows: &[Vec<&str>]) -> Vec<bool> {
+    let mut directions = vec![false; rows.len()];
+    let mut start = 0;
+    for (end, row) in rows.iter().enumerate() {
+        if row.last() != Some(&"↩") && end + 1 < rows.len() {
+            continue;
+        }
+        let rtl = rows[start..=end]
+            .iter()
+            .flatten()
+            .find_map(|g| direction(g))
+            .unwrap_or(false);
+        directions[start..=end].fill(rtl);
+        start = end + 1;
+    }
+    directions
+}
+
+// The order to draw a row's graphemes in, left to right on screen
+pub fn visual_order(row: &[&str], rtl: bool) -> Vec<usize> {
+    let base = usize::from(rtl);
+    // Text against the paragraph's direction is embedded one level up
+    let strong = row.iter().map(|g| direction(g)).collect::<Vec<_>>();
+    let levels = (0..row.len())
+        .map(|i| {
+            let before = strong[..=i].iter().rev().find_map(|d| *d);
+            let after = strong[i..].iter().find_map(|d| *d);
+            match (strong[i], before, after) {
+                (Some(d), ..) => level(d, base),
+                // Spaces between matching text take its direction
+                (None, Some(a), Some(b)) if a == b => level(a, base),
+                _ => base,
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut order = (0..row.len()).collect::<Vec<_>>();
+    let highest = levels.iter().copied().max().unwrap_or(0);
+    // Reverse each run from the highest level down to the lowest odd one
+    for current in (1..=highest).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < current {
+                i += 1;
+                continue;
+            }
+            let end = (i..order.len())
+                .find(|j| levels[order[*j]] < current)
+                .unwrap_or(order.len());
+            order[i..end].reverse();
+            i = end;
+        }
+    }
+    order
+}
+
+fn level(rtl: bool, base: usize) -> usize {
+    match (rtl, base) {
+        (true, 0) => 1,
+        (false, 1) => 2,
+        _ => base,
+    }
+}
@@ -2,74 +2,565 @@
 //
 // SPDX-License-Identifier: GPL-3.0-only
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{fs, io};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::engine::Options;
+use crate::session::Session;
 
 pub struct FileData {
-    progress_path: PathBuf,
+    pub story_path: PathBuf,
+    // Generated drills and piped stories aren't saved anywhere
+    progress_path: Option<PathBuf>,
+    history_path: Option<PathBuf>,
+    pub drill: bool,
+    // Freeform stories are just whatever has been typed
+    pub freeform: bool,
     pub progress: Progress,
     pub story: String,
+    // The byte offset of each grapheme in the story
+    graphemes: Vec<usize>,
+    pub story_len: usize,
+    pub glossary: HashMap<String, String>,
+    // Where each `## ` section starts and its title
+    pub sections: Vec<(usize, String)>,
+    pub warning: Option<String>,
+}
+
+// Name the file an error came from so it can be shown to the user
+fn in_file(path: &Path) -> impl FnOnce(io::Error) -> io::Error {
+    move |err| io::Error::new(err.kind(), format!("{}: {err}", path.display()))
+}
+
+// Drop everything but letters, turning breaks into single spaces
+fn letters_only(story: &str) -> String {
+    story
+        .split(|c: char| c == '↩' || c == '⇥' || c.is_whitespace())
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphabetic())
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Mark breaks and swap typographic punctuation in a single pass over the text
+fn normalize(text: &str, options: &Options) -> String {
+    let mut story = String::with_capacity(text.len());
+    for c in text.chars() {
+        let c = match c {
+            '\n' if options.newlines == Newlines::Space => ' ',
+            '\n' => '↩',
+            '\t' => '⇥',
+            // Use what's on the keyboard unless asked not to
+            '—' | '–' if !options.no_normalize => '-',
+            '’' if !options.no_normalize => '\'',
+            '“' | '”' if !options.no_normalize => '"',
+            '…' if !options.no_normalize => {
+                story.push_str("...");
+                continue;
+            }
+            c => c,
+        };
+        // Squeeze runs of spaces so each only needs typing once
+        if c == ' ' && options.collapse_spaces && story.ends_with(' ') {
+            continue;
+        }
+        story.push(c);
+    }
+    story
+}
+
+// Stdin can only be read once so keep it around for reopening
+fn read_stdin() -> io::Result<String> {
+    static STDIN: OnceLock<String> = OnceLock::new();
+    if let Some(text) = STDIN.get() {
+        return Ok(text.clone());
+    }
+    let text = io::read_to_string(io::stdin())?;
+    Ok(STDIN.get_or_init(|| text).clone())
+}
+
+// Each profile and filtered version of the story get their own progress
+fn sidecar(story_path: &Path, options: &Options, extension: &str) -> PathBuf {
+    let mut parts = vec![];
+    parts.extend(options.profile.as_deref());
+    if options.no_normalize {
+        parts.push("raw");
+    }
+    if options.collapse_spaces {
+        parts.push("collapsed");
+    }
+    if options.newlines == Newlines::Space {
+        parts.push("spaced");
+    }
+    if options.letters_only {
+        parts.push("letters");
+    }
+    parts.push(extension);
+    story_path.with_extension(parts.join("."))
+}
+
+// Summarise a story's progress without opening it
+pub fn stats(story_path: &Path, options: &Options) -> io::Result<serde_json::Value> {
+    fs::metadata(story_path).map_err(in_file(story_path))?;
+    let progress_path = sidecar(story_path, options, "progress.json");
+    let progress = match progress_path.exists() {
+        true => Progress::load(&progress_path)
+            .and_then(|loaded| Ok(loaded?))
+            .map_err(in_file(&progress_path))?,
+        false => Progress::default(),
+    };
+    // Every line after the header is a session
+    let history_path = sidecar(story_path, options, "history.csv");
+    let sessions = match fs::read_to_string(&history_path) {
+        Ok(history) => history.lines().skip(1).count(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+        Err(err) => return Err(in_file(&history_path)(err)),
+    };
+    Ok(serde_json::json!({
+        "story": story_path,
+        "chars": progress.chars,
+        "mistakes": progress.mistakes,
+        "skipped": progress.skipped,
+        "accuracy": progress.accuracy(),
+        "seconds": progress.seconds,
+        "best_wpm": progress.best_wpm,
+        "sessions": sessions,
+    }))
+}
+
+// The byte offset of each grapheme in the story
+fn grapheme_indices(story: &str) -> Vec<usize> {
+    story.grapheme_indices(true).map(|(i, _)| i).collect()
+}
+
+// Lines starting with `## ` mark the start of a section
+fn sections(story: &str, graphemes: &[usize]) -> Vec<(usize, String)> {
+    let mut sections = vec![];
+    for (i, start) in graphemes.iter().enumerate() {
+        if i > 0 && !story[..*start].ends_with('↩') {
+            continue;
+        }
+        if let Some(line) = story[*start..].strip_prefix("## ") {
+            let title = line.split('↩').next().unwrap_or_default();
+            sections.push((i, title.trim().to_string()));
+        }
+    }
+    sections
 }
 
 impl FileData {
-    pub fn load() -> io::Result<Self> {
-        // Parse the args
-        let cli_args = Cli::parse();
-        let story = fs::read_to_string(&cli_args.story)?
-            .replace("\n", "↩")
-            .replace("—", "-")
-            .replace("—", "-")
-            .replace("’", "'")
-            .replace("“", "\"")
-            .replace("”", "\"");
-        let progress_path = cli_args.story.with_extension("progress.json");
-        // Load the progress file
-        let progress = Progress::load(&progress_path)?;
+    pub fn open(story_path: &Path, options: &Options) -> io::Result<Self> {
+        let stdin = story_path == Path::new("-");
+        let text = match stdin {
+            true => read_stdin()?,
+            false => fs::read_to_string(story_path).map_err(in_file(story_path))?,
+        };
+        let mut story = normalize(&text, options);
+        if options.letters_only {
+            story = letters_only(&story);
+        }
+        // There's nothing to practice without something to type
+        if story
+            .chars()
+            .all(|c| c.is_whitespace() || c == '↩' || c == '⇥')
+        {
+            let err = io::Error::new(io::ErrorKind::InvalidData, "story has nothing to type");
+            return Err(in_file(story_path)(err));
+        }
+        let graphemes = grapheme_indices(&story);
+        let story_len = graphemes.len();
+        let sections = sections(&story, &graphemes);
+        let progress_path = sidecar(story_path, options, "progress.json");
+        let history_path = sidecar(story_path, options, "history.csv");
+        // Load the progress file, starting over if it's unreadable
+        let mut warning = None;
+        // Piped stories have nowhere to keep their progress
+        let loaded = match stdin {
+            true => Ok(Progress::default()),
+            false => Progress::load(&progress_path).map_err(in_file(&progress_path))?,
+        };
+        // Empty and half written files are what a crash leaves behind
+        let progress = loaded.unwrap_or_else(|err| {
+            warning = Some(format!("Ignoring malformed progress file ({err})"));
+            Progress::default()
+        });
+        // Load the glossary of word hints
+        let glossary = match &options.glossary {
+            Some(path) => fs::File::open(path)
+                .and_then(|file| Ok(serde_json::from_reader(file)?))
+                .map_err(in_file(path))?,
+            None => HashMap::new(),
+        };
         // Build the persistant state
         Ok(FileData {
-            progress_path,
+            story_path: story_path.to_path_buf(),
+            progress_path: (!stdin).then_some(progress_path),
+            history_path: (!stdin).then_some(history_path),
+            drill: false,
+            freeform: false,
             progress,
             story,
+            graphemes,
+            story_len,
+            glossary,
+            sections,
+            warning,
         })
     }
+    pub fn drill(story: String, story_path: &Path) -> Self {
+        let graphemes = grapheme_indices(&story);
+        FileData {
+            story_path: story_path.to_path_buf(),
+            progress_path: None,
+            history_path: None,
+            drill: true,
+            freeform: false,
+            progress: Progress::default(),
+            story_len: graphemes.len(),
+            story,
+            graphemes,
+            glossary: HashMap::new(),
+            sections: vec![],
+            warning: None,
+        }
+    }
+    pub fn freeform() -> Self {
+        Self {
+            drill: false,
+            freeform: true,
+            ..Self::drill(String::new(), Path::new(""))
+        }
+    }
+    pub fn push(&mut self, c: char) {
+        self.graphemes.push(self.story.len());
+        self.story.push(c);
+        self.story_len += 1;
+    }
+    pub fn truncate(&mut self, len: usize) {
+        if let Some(end) = self.graphemes.get(len).copied() {
+            self.story.truncate(end);
+            self.graphemes.truncate(len);
+            self.story_len = len;
+        }
+    }
+    pub fn apply_start(&mut self, options: &Options) -> io::Result<()> {
+        // Start over if asked, saving straight away
+        if options.reset {
+            self.progress.reset();
+            self.save()?;
+        }
+        // Jump ahead to the requested starting point
+        let start = match (options.start_line, options.start_percent) {
+            (Some(line), _) => self.line_start(line),
+            (_, Some(percent)) => {
+                (self.story_len as f64 * percent.clamp(0.0, 100.0) / 100.0) as usize
+            }
+            _ => 0,
+        };
+        self.progress.chars = self.progress.chars.max(start);
+        Ok(())
+    }
+    fn line_start(&self, line: usize) -> usize {
+        // Lines are counted from one
+        let breaks = line.saturating_sub(1);
+        if breaks == 0 {
+            return 0;
+        }
+        (0..self.story_len)
+            .filter(|i| self.grapheme(*i) == Some("↩"))
+            .nth(breaks - 1)
+            .map_or(self.story_len, |i| i + 1)
+    }
+    // The section a character falls in, if it's past the first marker
+    pub fn section_at(&self, i: usize) -> Option<usize> {
+        self.sections.iter().rposition(|(start, _)| *start <= i)
+    }
+    pub fn grapheme(&self, i: usize) -> Option<&str> {
+        let start = *self.graphemes.get(i)?;
+        let end = self.graphemes.get(i + 1).copied();
+        Some(&self.story[start..end.unwrap_or(self.story.len())])
+    }
+    pub fn completion(&self) -> f64 {
+        if self.story_len == 0 {
+            return 1.0;
+        }
+        (self.progress.chars as f64 / self.story_len as f64).min(1.0)
+    }
     pub fn save(&self) -> io::Result<()> {
-        self.progress.save(&self.progress_path)
+        match &self.progress_path {
+            Some(path) => self.progress.save(path),
+            None => Ok(()),
+        }
+    }
+    pub fn log_session(&self, session: &Session, record: bool) -> io::Result<()> {
+        // Sessions without any typing aren't worth recording
+        let Some(path) = &self.history_path else {
+            return Ok(());
+        };
+        if session.correct + session.mistakes == 0 {
+            return Ok(());
+        }
+        let new = !path.exists();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(in_file(path))?;
+        if new {
+            writeln!(file, "timestamp,chars,mistakes,seconds,wpm,record")?;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        writeln!(
+            file,
+            "{timestamp},{},{},{:.1},{:.1},{record}",
+            session.correct,
+            session.mistakes,
+            session.elapsed().as_secs_f64(),
+            session.wpm().unwrap_or(0.0)
+        )
     }
 }
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Progress {
     pub chars: usize,
+    pub mistakes: usize,
+    pub skipped: usize,
+    pub seconds: u64,
+    pub best_wpm: f64,
+    pub layout: String,
+    pub section: Option<usize>,
+    // Sorted so the file doesn't shuffle between saves
+    pub misses: BTreeMap<char, usize>,
+    pub pair_misses: BTreeMap<String, usize>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            chars: 0,
+            mistakes: 0,
+            skipped: 0,
+            seconds: 0,
+            best_wpm: 0.0,
+            layout: "QWERTY".to_string(),
+            section: None,
+            misses: BTreeMap::new(),
+            pair_misses: BTreeMap::new(),
+        }
+    }
 }
 
 impl Progress {
-    fn load(path: &Path) -> io::Result<Self> {
+    pub fn reset(&mut self) {
+        // Keep the layout since it's a preference rather than progress
+        let layout = std::mem::take(&mut self.layout);
+        *self = Self {
+            layout,
+            ..Self::default()
+        };
+    }
+    pub fn accuracy(&self) -> f64 {
+        let attempts = self.chars + self.mistakes;
+        if attempts == 0 {
+            return 100.0;
+        }
+        self.chars as f64 / attempts as f64 * 100.0
+    }
+    // Malformed files are kept apart from real I/O errors so they can be ignored
+    fn load(path: &Path) -> io::Result<Result<Self, serde_json::Error>> {
         // Ensure the file exists
         if !path.exists() {
             let mut file = std::fs::File::create_new(path)?;
-            let content = serde_json::to_string_pretty(&Progress::default()).unwrap();
+            let content = serde_json::to_string_pretty(&Progress::default())?;
             file.write_all(content.as_bytes())?;
         }
-        // Read the config file
-        Ok(serde_json::from_reader(fs::File::open(path)?).unwrap())
+        // Read the progress file
+        match serde_json::from_reader(fs::File::open(path)?) {
+            Err(err) if err.is_io() => Err(err.into()),
+            loaded => Ok(loaded),
+        }
     }
     fn save(&self, path: &Path) -> io::Result<()> {
-        // Overwrite the file
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(path)?;
-        // With the current progress
-        file.write_all(serde_json::to_string_pretty(&self).unwrap().as_bytes())
+        // Write the progress beside the file first
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(serde_json::to_string_pretty(&self)?.as_bytes())?;
+        file.sync_all()?;
+        // Then swap it in so a crash never leaves half a file
+        fs::rename(&temp, path)
     }
 }
 
+// What to do about line breaks in the story
+#[derive(Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Newlines {
+    // Press enter for each one
+    #[default]
+    Enter,
+    // Show them but move straight past
+    Skip,
+    // Type them as spaces
+    Space,
+}
+
 #[derive(Parser)]
-struct Cli {
+pub struct Cli {
     #[arg(long)]
     progress: Option<PathBuf>,
-    story: PathBuf,
+    /// A JSON file mapping words to hints shown while typing them
+    #[arg(long)]
+    glossary: Option<PathBuf>,
+    /// Skip the celebration when a story is finished
+    #[arg(long)]
+    pub no_animations: bool,
+    /// Ring the terminal bell on mistakes
+    #[arg(long)]
+    pub bell: bool,
+    /// A JSON file describing a layout of your own to practice on
+    #[arg(long)]
+    pub layout_file: Option<PathBuf>,
+    /// Use starker colors, thicker borders and bigger keys
+    #[arg(long)]
+    pub high_contrast: bool,
+    /// Start with the keyboard hidden
+    #[arg(long)]
+    pub no_keyboard: bool,
+    /// Show what was actually typed under each line of the story
+    #[arg(long)]
+    pub diff: bool,
+    /// Stay on a character until it's typed right, with no skipping
+    #[arg(long)]
+    pub strict: bool,
+    /// Accept lowercase letters for uppercase ones
+    #[arg(long)]
+    pub ignore_case: bool,
+    /// How many characters wide to show the story, up to the window width
+    #[arg(long)]
+    pub context: Option<u16>,
+    /// Faintly light up this many keys after the next one
+    #[arg(long, default_value_t = 0)]
+    pub peek: usize,
+    /// Pace a ghost through the story at this many words per minute
+    #[arg(long)]
+    pub target_wpm: Option<f64>,
+    /// Race a run saved with --save-ghost
+    #[arg(long)]
+    pub ghost: Option<PathBuf>,
+    /// Save this run so it can be raced with --ghost
+    #[arg(long)]
+    pub save_ghost: Option<PathBuf>,
+    /// How many characters long a generated drill should be
+    #[arg(long, default_value_t = 200)]
+    pub drill_length: usize,
+    /// Type this many random common words instead of a story
+    #[arg(long, conflicts_with = "story")]
+    pub words: Option<usize>,
+    /// Retype each line until it's typed with this accuracy
+    #[arg(long)]
+    pub drill_accuracy: Option<f64>,
+    /// Keep separate progress under this name
+    #[arg(long)]
+    profile: Option<String>,
+    /// Keep dashes, curly quotes and ellipses instead of swapping them for ASCII
+    #[arg(long)]
+    no_normalize: bool,
+    /// How to type line breaks in the story
+    #[arg(long, value_enum, default_value_t = Newlines::Enter)]
+    pub newlines: Newlines,
+    /// Turn runs of spaces in the story into single spaces
+    #[arg(long)]
+    collapse_spaces: bool,
+    /// Practice only the letters and spaces in the story
+    #[arg(long)]
+    pub letters_only: bool,
+    /// Stop after this many seconds, counting from the first keystroke
+    #[arg(long)]
+    pub duration: Option<u64>,
+    /// Print the story's progress as JSON instead of practicing
+    #[arg(long, requires = "story")]
+    pub stats: bool,
+    /// End the session once this many characters are typed
+    #[arg(long, conflicts_with = "goal_time")]
+    pub goal_chars: Option<usize>,
+    /// End the session after this many seconds of typing
+    #[arg(long)]
+    pub goal_time: Option<u64>,
+    /// Show the keys missed most last time before starting
+    #[arg(long)]
+    pub report: bool,
+    /// Clear the story's progress before starting
+    #[arg(long)]
+    reset: bool,
+    /// Start from this line unless already past it
+    #[arg(long, conflicts_with = "start_percent")]
+    start_line: Option<usize>,
+    /// Start this far through the story unless already past it
+    #[arg(long)]
+    start_percent: Option<f64>,
+    /// A story file, a directory of stories, or - for stdin, leave out to type freely
+    pub story: Option<PathBuf>,
+}
+
+impl Cli {
+    // The engine's share of the arguments
+    pub fn options(&self) -> Options {
+        Options {
+            glossary: self.glossary.clone(),
+            profile: self.profile.clone(),
+            no_normalize: self.no_normalize,
+            newlines: self.newlines,
+            collapse_spaces: self.collapse_spaces,
+            letters_only: self.letters_only,
+            reset: self.reset,
+            start_line: self.start_line,
+            start_percent: self.start_percent,
+            words: self.words,
+            strict: self.strict,
+            ignore_case: self.ignore_case,
+            drill_accuracy: self.drill_accuracy,
+            duration: self.duration,
+            goal_chars: self.goal_chars,
+            goal_time: self.goal_time,
+            target_wpm: self.target_wpm,
+            ghost: self.ghost.clone(),
+            save_ghost: self.save_ghost.clone(),
+            idle: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_progress_is_not_an_io_error() {
+        let dir = std::env::temp_dir().join(format!("maddi-type-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("story.progress.json");
+        for content in ["", "{\"chars\": 3", "garbage"] {
+            fs::write(&path, content).unwrap();
+            let loaded = Progress::load(&path).unwrap();
+            assert!(loaded.is_err(), "{content:?} should be malformed");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,264 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use std::{env, fs, io};
+
+use crate::{
+    THEME_CONTRAST_BASE, THEME_CONTRAST_HINT, THEME_CONTRAST_UPCOMING, THEME_KEY_BASE,
+    THEME_KEY_HINT, THEME_KEY_UPCOMING, Theme,
+};
+use ratatui::{
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    style::Color,
+};
+use serde::Deserialize;
+
+pub struct Config {
+    pub base: Theme,
+    pub hint: Theme,
+    pub upcoming: Theme,
+    pub keys: Keys,
+    pub blink: bool,
+    pub accuracy: Accuracy,
+    // How often to save progress while practicing, if at all
+    pub autosave: Option<Duration>,
+    pub high_contrast: bool,
+    // How long without a keystroke before the timer stops
+    pub idle: Option<Duration>,
+    // Set themes win over the colors layouts bring with them
+    pub themed: bool,
+}
+
+// Seconds between saves unless the config says otherwise
+const AUTOSAVE: u64 = 30;
+
+impl Config {
+    pub fn load() -> io::Result<Self> {
+        let Some(path) = Self::path().filter(|path| path.exists()) else {
+            return Ok(Self::default());
+        };
+        let invalid = |err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {err}", path.display()),
+            )
+        };
+        let file: ConfigFile = serde_json::from_reader(fs::File::open(&path)?)
+            .map_err(|err| invalid(err.to_string()))?;
+        Ok(Self {
+            base: file.theme.base.apply(THEME_KEY_BASE).map_err(invalid)?,
+            hint: file.theme.hint.apply(THEME_KEY_HINT).map_err(invalid)?,
+            upcoming: file
+                .theme
+                .upcoming
+                .apply(THEME_KEY_UPCOMING)
+                .map_err(invalid)?,
+            keys: file.keys,
+            blink: file.blink.unwrap_or(true),
+            accuracy: file.accuracy,
+            autosave: autosave(file.autosave),
+            high_contrast: false,
+            idle: file.idle.map(Duration::from_secs),
+            themed: [&file.theme.base, &file.theme.hint, &file.theme.upcoming]
+                .iter()
+                .any(|theme| theme.is_set()),
+        })
+    }
+    // Swap in starker colors, ignoring any configured theme
+    pub fn use_high_contrast(&mut self) {
+        self.base = THEME_CONTRAST_BASE;
+        self.hint = THEME_CONTRAST_HINT;
+        self.upcoming = THEME_CONTRAST_UPCOMING;
+        self.high_contrast = true;
+        self.themed = true;
+    }
+    fn path() -> Option<PathBuf> {
+        // Follow the XDG base directory spec
+        let dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("maddi-type").join("config.json"))
+    }
+}
+
+// What you get without a config file
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            base: THEME_KEY_BASE,
+            hint: THEME_KEY_HINT,
+            upcoming: THEME_KEY_UPCOMING,
+            keys: Keys::default(),
+            blink: true,
+            accuracy: Accuracy::default(),
+            autosave: autosave(None),
+            high_contrast: false,
+            idle: None,
+            themed: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ConfigFile {
+    theme: ThemesFile,
+    keys: Keys,
+    blink: Option<bool>,
+    accuracy: Accuracy,
+    autosave: Option<u64>,
+    idle: Option<u64>,
+}
+
+// Zero seconds turns saving along the way off
+fn autosave(seconds: Option<u64>) -> Option<Duration> {
+    let seconds = seconds.unwrap_or(AUTOSAVE);
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+// Accuracy percentages above which the WPM shows green or yellow
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct Accuracy {
+    pub good: f64,
+    pub fair: f64,
+}
+
+impl Default for Accuracy {
+    fn default() -> Self {
+        Self {
+            good: 97.0,
+            fair: 93.0,
+        }
+    }
+}
+
+impl Accuracy {
+    pub fn color(&self, accuracy: f64) -> Color {
+        if accuracy > self.good {
+            Color::Green
+        } else if accuracy > self.fair {
+            Color::Yellow
+        } else {
+            Color::Red
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ThemesFile {
+    base: ThemeFile,
+    hint: ThemeFile,
+    upcoming: ThemeFile,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ThemeFile {
+    text: Option<String>,
+    background: Option<String>,
+    highlight: Option<String>,
+    shadow: Option<String>,
+}
+
+impl ThemeFile {
+    fn is_set(&self) -> bool {
+        self.text.is_some()
+            || self.background.is_some()
+            || self.highlight.is_some()
+            || self.shadow.is_some()
+    }
+    fn apply(&self, theme: Theme) -> Result<Theme, String> {
+        let color = |color: &Option<String>, default| match color {
+            Some(color) => Color::from_str(color).map_err(|_| format!("invalid color {color:?}")),
+            None => Ok(default),
+        };
+        Ok(Theme {
+            text: color(&self.text, theme.text)?,
+            background: color(&self.background, theme.background)?,
+            highlight: color(&self.highlight, theme.highlight)?,
+            shadow: color(&self.shadow, theme.shadow)?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Keys {
+    pub toggle_hints: Binding,
+    pub next_layout: Binding,
+    pub exit: Binding,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self {
+            toggle_hints: Binding::control('h'),
+            next_layout: Binding::control('n'),
+            exit: Binding {
+                code: KeyCode::Esc,
+                modifiers: KeyModifiers::NONE,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "String")]
+pub struct Binding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Binding {
+    pub const fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }
+    }
+    pub const fn control(c: char) -> Self {
+        Self::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+    pub fn matches(&self, key_event: &KeyEvent) -> bool {
+        key_event.code == self.code && key_event.modifiers.contains(self.modifiers)
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+    // Bindings are written like "C-h", "Esc" or "q"
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (modifiers, key) = match value.strip_prefix("C-") {
+            Some(key) => (KeyModifiers::CONTROL, key),
+            None => (KeyModifiers::NONE, value.as_str()),
+        };
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ if key.eq_ignore_ascii_case("esc") => KeyCode::Esc,
+            _ if key.eq_ignore_ascii_case("enter") => KeyCode::Enter,
+            _ if key.eq_ignore_ascii_case("tab") => KeyCode::Tab,
+            _ => return Err(format!("invalid key binding {value:?}")),
+        };
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.modifiers.contains(KeyModifiers::CONTROL) {
+            "C-"
+        } else {
+            ""
+        };
+        match self.code {
+            KeyCode::Char(c) => write!(f, "<{prefix}{c}>"),
+            code => write!(f, "<{prefix}{code}>"),
+        }
+    }
+}
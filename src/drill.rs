@@ -0,0 +1,88 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Letters that fill out the words around the weak keys
+const COMMON: [char; 9] = ['e', 't', 'a', 'o', 'i', 'n', 's', 'h', 'r'];
+
+// How many of the most missed characters to drill at once
+const WORST: usize = 6;
+
+// The most common English words, one per line
+const WORDS: &str = include_str!("words.txt");
+
+struct Random(u64);
+
+impl Random {
+    fn seeded() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Self(nanos as u64)
+    }
+    fn below(&mut self, n: usize) -> usize {
+        // A simple linear congruential generator is plenty for drills
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize % n
+    }
+}
+
+pub fn generate(misses: &BTreeMap<char, usize>, length: usize) -> String {
+    let mut worst = misses
+        .iter()
+        .filter(|(c, _)| !c.is_whitespace() && **c != '↩' && **c != '⇥')
+        .map(|(c, count)| (*c, *count))
+        .collect::<Vec<_>>();
+    worst.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    worst.truncate(WORST);
+    let worst = worst.into_iter().map(|(c, _)| c).collect::<Vec<_>>();
+    words(&worst, length)
+}
+
+pub fn layers(layers: &[&[&[char]]], length: usize) -> String {
+    let chars = layers
+        .iter()
+        .flat_map(|layer| layer.iter())
+        .flat_map(|row| row.iter())
+        .copied()
+        .filter(|c| *c != '\0')
+        .collect::<Vec<_>>();
+    words(&chars, length)
+}
+
+// A quick typing test out of common words
+pub fn generate_passage(word_count: usize) -> String {
+    let words = WORDS.lines().collect::<Vec<_>>();
+    let mut random = Random::seeded();
+    (0..word_count.max(1))
+        .map(|_| words[random.below(words.len())])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn words(focus: &[char], length: usize) -> String {
+    // Build short words mostly out of the focus characters
+    let mut random = Random::seeded();
+    let mut drill = String::new();
+    while drill.chars().count() < length {
+        if !drill.is_empty() {
+            drill.push(' ');
+        }
+        for _ in 0..3 + random.below(4) {
+            let c = if !focus.is_empty() && random.below(3) < 2 {
+                focus[random.below(focus.len())]
+            } else {
+                COMMON[random.below(COMMON.len())]
+            };
+            drill.push(c);
+        }
+    }
+    drill
+}
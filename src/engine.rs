@@ -0,0 +1,697 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::cli::{FileData, Newlines};
+use crate::layout::{self, Layout, Modifier};
+use crate::session::{Session, format_duration};
+use crate::{drill, ghost};
+
+// Everything the engine needs to know, however it was asked for
+#[derive(Clone, Default)]
+pub struct Options {
+    // How the story is read and where its progress is kept
+    pub glossary: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub no_normalize: bool,
+    pub newlines: Newlines,
+    pub collapse_spaces: bool,
+    pub letters_only: bool,
+    // Where to start in it
+    pub reset: bool,
+    pub start_line: Option<usize>,
+    pub start_percent: Option<f64>,
+    // Random common words to type instead of a story
+    pub words: Option<usize>,
+    // How it's typed
+    pub strict: bool,
+    pub ignore_case: bool,
+    pub drill_accuracy: Option<f64>,
+    pub duration: Option<u64>,
+    pub goal_chars: Option<usize>,
+    pub goal_time: Option<u64>,
+    pub target_wpm: Option<f64>,
+    pub ghost: Option<PathBuf>,
+    pub save_ghost: Option<PathBuf>,
+    // How long without a keystroke before the timer stops
+    pub idle: Option<Duration>,
+}
+
+// A wrong key left in place of the character it was meant to be
+pub struct Mistake {
+    pub char: char,
+    pub position: usize,
+}
+
+// The current go at a line when drilling for accuracy
+struct Attempt {
+    start: usize,
+    correct: usize,
+    mistakes: usize,
+}
+
+impl Attempt {
+    fn accuracy(&self) -> f64 {
+        let attempts = self.correct + self.mistakes;
+        if attempts == 0 {
+            return 100.0;
+        }
+        self.correct as f64 / attempts as f64 * 100.0
+    }
+}
+
+// Words are broken up by whitespace, line breaks and indents
+fn is_break(g: &str) -> bool {
+    g == "↩" || g == "⇥" || g.chars().all(char::is_whitespace)
+}
+
+// Combining marks have no key, so a base letter followed by them stands
+// in for the whole grapheme
+fn base_char(g: &str) -> Option<char> {
+    let mut chars = g.chars();
+    let base = chars.next()?;
+    chars.all(is_combining).then_some(base)
+}
+
+fn is_combining(c: char) -> bool {
+    matches!(
+        c,
+        '\u{300}'..='\u{36f}'
+            | '\u{1ab0}'..='\u{1aff}'
+            | '\u{1dc0}'..='\u{1dff}'
+            | '\u{20d0}'..='\u{20ff}'
+            | '\u{fe20}'..='\u{fe2f}'
+    )
+}
+
+fn grapheme_matches(expected: &str, typed: char, ignore_case: bool) -> bool {
+    let same = |expected: &str| match ignore_case {
+        true => expected.to_lowercase() == typed.to_lowercase().to_string(),
+        false => expected == typed.to_string(),
+    };
+    same(expected) || base_char(expected).is_some_and(|base| same(&base.to_string()))
+}
+
+// A story being typed, with everything about the run so far
+pub struct Engine {
+    pub options: Options,
+    pub file_data: FileData,
+    // The layout keystrokes are counted against
+    pub layout: &'static Layout,
+    pub session: Session,
+    pub mistakes: Vec<Mistake>,
+    // Whether each position was typed right first time, if typed this run
+    pub first_tries: Vec<Option<bool>>,
+    // The key last pressed at each position, if typed this run
+    pub typed: Vec<Option<char>>,
+    attempt: Option<Attempt>,
+    // The accuracy of the last line that had to be typed again
+    pub retry: Option<f64>,
+    pub skips: Vec<usize>,
+    // What moving forward passed over without typing
+    jumped: Vec<Range<usize>>,
+    // Whether the last keystroke was right and when it came
+    pub feedback: Option<(bool, Instant)>,
+    // Where strict mode is holding until the right key is pressed
+    pub stuck: Option<usize>,
+    pub deadline: Option<Instant>,
+    pub completed: bool,
+    // Where the ghost set off and the session time it left at
+    ghost_from: Option<(usize, Duration)>,
+    // The run being recorded and the one being raced
+    run: ghost::Run,
+    rival: Option<ghost::Run>,
+}
+
+impl Engine {
+    pub fn load(story: Option<&Path>, options: Options) -> io::Result<Self> {
+        let file_data = match story {
+            Some(story) => {
+                let mut file_data = FileData::open(story, &options)?;
+                file_data.apply_start(&options)?;
+                file_data
+            }
+            None => match options.words {
+                Some(words) => FileData::drill(drill::generate_passage(words), Path::new("")),
+                None => FileData::freeform(),
+            },
+        };
+        Self::new(file_data, options)
+    }
+    pub fn new(file_data: FileData, options: Options) -> io::Result<Self> {
+        let rival = options.ghost.as_deref().map(ghost::Run::load).transpose()?;
+        Ok(Self {
+            layout: &layout::LAYOUT_QWERTY,
+            session: Session::new(options.idle),
+            file_data,
+            mistakes: vec![],
+            first_tries: vec![],
+            typed: vec![],
+            attempt: None,
+            retry: None,
+            skips: vec![],
+            jumped: vec![],
+            feedback: None,
+            stuck: None,
+            deadline: None,
+            completed: false,
+            ghost_from: None,
+            run: ghost::Run::default(),
+            rival,
+            options,
+        })
+    }
+    pub fn position(&self) -> usize {
+        self.file_data.progress.chars
+    }
+    fn position_mut(&mut self) -> &mut usize {
+        &mut self.file_data.progress.chars
+    }
+    pub fn next(&self) -> Option<&str> {
+        self.file_data.grapheme(self.position())
+    }
+    fn advance(&mut self) {
+        if self.next().is_none() {
+            return;
+        }
+        *self.position_mut() += 1;
+        self.pass_newlines();
+        if self.next().is_none() && !self.file_data.freeform {
+            self.completed = true;
+        }
+    }
+    pub fn remaining(&self) -> Option<Duration> {
+        let seconds = self.options.duration?;
+        Some(match self.deadline {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+            None => Duration::from_secs(seconds),
+        })
+    }
+    pub fn time_up(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+    pub fn goal(&self) -> Option<String> {
+        // Describe how far through the session goal we are
+        if let Some(chars) = self.options.goal_chars {
+            return Some(format!("{}/{chars}", self.session.correct.min(chars)));
+        }
+        let goal = Duration::from_secs(self.options.goal_time?);
+        let elapsed = self.session.elapsed().min(goal);
+        Some(format!(
+            "{}/{}",
+            format_duration(elapsed),
+            format_duration(goal)
+        ))
+    }
+    pub fn goal_met(&self) -> bool {
+        match (self.options.goal_chars, self.options.goal_time) {
+            (Some(chars), _) => self.session.correct >= chars,
+            (_, Some(seconds)) => self.session.elapsed() >= Duration::from_secs(seconds),
+            _ => false,
+        }
+    }
+    pub fn advance_with(&mut self, c: char) {
+        self.retry = None;
+        // Freeform text is whatever gets typed
+        if self.file_data.freeform {
+            self.file_data.truncate(self.position());
+            self.file_data.push(c);
+        }
+        // The story might start on a line break or have been reopened on one
+        self.pass_newlines();
+        if self.next().is_none() {
+            return;
+        }
+        if let Some(location) = self.layout.location(c) {
+            let finger = self.layout.finger(&location);
+            self.session.fingers[finger as usize] += 1;
+        }
+        // The countdown starts with the first keystroke
+        if let Some(seconds) = self.options.duration
+            && self.deadline.is_none()
+        {
+            self.deadline = Some(Instant::now() + Duration::from_secs(seconds));
+        }
+        // The ghost sets off alongside the first keystroke
+        if self.ghost_from.is_none() {
+            self.ghost_from = Some((self.position(), self.session.elapsed()));
+        }
+        let correct = self.next().is_some_and(|next| self.matches(next, c));
+        self.feedback = Some((correct, Instant::now()));
+        // Only the first attempt at each character counts
+        let position = self.position();
+        if self.first_tries.len() <= position {
+            self.first_tries.resize(position + 1, None);
+        }
+        self.first_tries[position].get_or_insert(correct);
+        if self.typed.len() <= position {
+            self.typed.resize(position + 1, None);
+        }
+        self.typed[position] = Some(c);
+        // Tally accuracy by the row the target character sits on
+        let layout = self.layout;
+        if let Some(row) = self
+            .next()
+            .and_then(|next| layout.location(next.chars().next()?))
+            .and_then(|location| layout.row_index(&location))
+        {
+            let (attempts, misses) = &mut self.session.rows[row];
+            *attempts += 1;
+            *misses += usize::from(!correct);
+        }
+        if !correct {
+            // Strict mode doesn't move on so there's nothing to show in place
+            if !self.options.strict {
+                self.mistakes.push(Mistake {
+                    char: c,
+                    position: self.position(),
+                });
+            }
+            self.file_data.progress.mistakes += 1;
+            self.session.record_mistake();
+            // Count the miss against the key that should have been pressed
+            if let Some(expected) = self.next().and_then(|next| next.chars().next()) {
+                *self.file_data.progress.misses.entry(expected).or_default() += 1;
+                // And against the pair it finishes
+                let previous = self.position().checked_sub(1);
+                if let Some(previous) = previous
+                    .and_then(|i| self.file_data.grapheme(i))
+                    .and_then(|previous| previous.chars().next())
+                {
+                    *self
+                        .session
+                        .pair_misses
+                        .entry((previous, expected))
+                        .or_default() += 1;
+                    let pair = String::from_iter([previous, expected]);
+                    *self.file_data.progress.pair_misses.entry(pair).or_default() += 1;
+                }
+            }
+        } else {
+            self.session.record_correct(c);
+        }
+        if self.options.drill_accuracy.is_some() && !self.file_data.freeform {
+            let start = self.line_start();
+            let attempt = self.attempt.get_or_insert(Attempt {
+                start,
+                correct: 0,
+                mistakes: 0,
+            });
+            match correct {
+                true => attempt.correct += 1,
+                false => attempt.mistakes += 1,
+            }
+        }
+        if self.options.strict && !correct {
+            self.stuck = Some(self.position());
+            return;
+        }
+        self.stuck = None;
+        self.advance();
+        self.finish_attempt();
+    }
+    fn finish_attempt(&mut self) {
+        let Some(target) = self.options.drill_accuracy else {
+            return;
+        };
+        // Lines end on a line break or the end of the story
+        let position = self.position();
+        let line_end = position
+            .checked_sub(1)
+            .is_some_and(|i| self.file_data.grapheme(i) == Some("↩"));
+        if !line_end && self.next().is_some() {
+            return;
+        }
+        let Some(attempt) = self.attempt.take() else {
+            return;
+        };
+        let accuracy = attempt.accuracy();
+        if accuracy >= target {
+            return;
+        }
+        // Go again from the top of the line
+        self.completed = false;
+        self.move_to(attempt.start);
+        self.first_tries.truncate(attempt.start);
+        self.typed.truncate(attempt.start);
+        self.retry = Some(accuracy);
+    }
+    pub fn skip(&mut self) {
+        // Strict mode only skips what the layout can't type
+        if self.next().is_none() || (self.options.strict && !self.untypeable()) {
+            return;
+        }
+        self.skips.push(self.position());
+        self.file_data.progress.skipped += 1;
+        self.session.skipped += 1;
+        self.advance();
+    }
+    pub fn combination(&self) -> Option<String> {
+        let c = self.next()?.chars().next()?;
+        if self.options.ignore_case && c.is_uppercase() {
+            return None;
+        }
+        let layout = self.layout;
+        let location = layout.location(c)?;
+        let modifier = match location.modifier? {
+            Modifier::Shift => "shift",
+            Modifier::Sym => "sym",
+            Modifier::Cur => "cur",
+        };
+        let key = layout
+            .base
+            .get(location.row as usize)?
+            .get(location.col as usize)?;
+        Some(format!("Hold {modifier} and press {key}"))
+    }
+    pub fn untypeable(&self) -> bool {
+        let Some(next) = self.next() else {
+            return false;
+        };
+        let Some(c) = base_char(next) else {
+            return true;
+        };
+        let c = match self.options.ignore_case {
+            true => c.to_lowercase().next().unwrap_or(c),
+            false => c,
+        };
+        !c.is_whitespace() && c != '↩' && c != '⇥' && self.layout.location(c).is_none()
+    }
+    pub fn matches(&self, expected: &str, typed: char) -> bool {
+        grapheme_matches(expected, typed, self.options.ignore_case)
+    }
+    fn pass_newlines(&mut self) {
+        if self.options.newlines != Newlines::Skip {
+            return;
+        }
+        while self.next() == Some("↩") {
+            *self.position_mut() += 1;
+        }
+    }
+    pub fn backspace(&mut self) {
+        if self.position() == 0 {
+            return;
+        }
+        *self.position_mut() -= 1;
+        // Passed line breaks were never typed so go back over them too
+        while self.options.newlines == Newlines::Skip
+            && self.position() > 0
+            && self.next() == Some("↩")
+        {
+            *self.position_mut() -= 1;
+        }
+        self.feedback = None;
+        let position = self.position();
+        if self.file_data.freeform {
+            self.file_data.truncate(position);
+        }
+        // Characters being retyped shouldn't count twice towards the WPM
+        if self.mistakes.iter().any(|e| e.position == position) {
+            self.mistakes.retain(|e| e.position != position);
+        } else if self.skips.contains(&position) {
+            self.skips.retain(|skip| *skip != position);
+        } else if self.jumped.iter().any(|jump| jump.contains(&position)) {
+            self.move_to(position);
+        } else {
+            self.session.correct = self.session.correct.saturating_sub(1);
+        }
+    }
+    pub fn ghost(&self) -> Option<usize> {
+        self.ghost_at(self.session.elapsed())
+    }
+    pub fn ghost_at(&self, elapsed: Duration) -> Option<usize> {
+        let (start, offset) = self.ghost_from?;
+        let elapsed = elapsed.saturating_sub(offset);
+        // Race a recorded run if there is one, otherwise the target speed
+        let chars = match (&self.rival, self.options.target_wpm) {
+            (Some(rival), _) => rival.chars_at(elapsed),
+            (None, Some(wpm)) => (elapsed.as_secs_f64() / 60.0 * wpm * 5.0) as usize,
+            (None, None) => return None,
+        };
+        Some((start + chars).min(self.file_data.story_len))
+    }
+    pub fn record_run(&mut self) {
+        if let Some((start, offset)) = self.ghost_from {
+            let elapsed = self.session.elapsed().saturating_sub(offset);
+            self.run
+                .record(self.position().saturating_sub(start), elapsed);
+        }
+    }
+    fn breaks_at(&self, i: usize) -> bool {
+        self.file_data.grapheme(i).is_some_and(is_break)
+    }
+    pub fn move_to(&mut self, position: usize) {
+        let from = self.position();
+        *self.position_mut() = position.min(self.file_data.story_len);
+        self.feedback = None;
+        // Anything after the new position is untyped again
+        let position = self.position();
+        self.mistakes.retain(|e| e.position < position);
+        self.skips.retain(|skip| *skip < position);
+        for jump in &mut self.jumped {
+            jump.end = jump.end.min(position);
+        }
+        self.jumped.retain(|jump| !jump.is_empty());
+        if position > from {
+            self.jumped.push(from..position);
+        }
+    }
+    pub fn next_word(&self) -> usize {
+        let position = self.position();
+        let story_len = self.file_data.story_len;
+        let end = (position..story_len)
+            .find(|i| self.breaks_at(*i))
+            .unwrap_or(story_len);
+        (end..story_len)
+            .find(|i| !self.breaks_at(*i))
+            .unwrap_or(story_len)
+    }
+    pub fn previous_word(&self) -> usize {
+        let position = self.position();
+        let word = (0..position)
+            .rev()
+            .find(|i| !self.breaks_at(*i))
+            .unwrap_or(0);
+        (0..word)
+            .rev()
+            .find(|i| self.breaks_at(*i))
+            .map_or(0, |i| i + 1)
+    }
+    pub fn next_section(&self) -> usize {
+        let position = self.position();
+        self.file_data
+            .sections
+            .iter()
+            .map(|(start, _)| *start)
+            .find(|start| *start > position)
+            .unwrap_or(self.file_data.story_len)
+    }
+    pub fn previous_section(&self) -> usize {
+        // Go back to the start of this section before the one before it
+        let position = self.position();
+        self.file_data
+            .sections
+            .iter()
+            .map(|(start, _)| *start)
+            .rfind(|start| *start < position)
+            .unwrap_or(0)
+    }
+    pub fn line_start(&self) -> usize {
+        (0..self.position())
+            .rev()
+            .find(|i| self.file_data.grapheme(*i) == Some("↩"))
+            .map_or(0, |i| i + 1)
+    }
+    pub fn indent(&mut self) {
+        // Only leading spaces count as an indent
+        let position = self.position();
+        let leading =
+            (self.line_start()..position).all(|i| self.file_data.grapheme(i) == Some(" "));
+        let spaces = (position..)
+            .take_while(|i| self.file_data.grapheme(*i) == Some(" "))
+            .count();
+        if !leading || spaces == 0 {
+            self.advance_with('⇥');
+            return;
+        }
+        // The whole indent counts as a single keystroke
+        self.advance_with(' ');
+        if self.position() > position {
+            self.move_to(position + spaces);
+        }
+    }
+    pub fn line_end(&self) -> usize {
+        let story_len = self.file_data.story_len;
+        (self.position()..story_len)
+            .find(|i| self.file_data.grapheme(*i) == Some("↩"))
+            .unwrap_or(story_len)
+    }
+    pub fn current_word_range(&self) -> Option<Range<usize>> {
+        let position = self.position();
+        if self.file_data.grapheme(position).is_none_or(is_break) {
+            return None;
+        }
+        // Walk outwards to the surrounding word breaks
+        let start = (0..position)
+            .rev()
+            .find(|i| self.file_data.grapheme(*i).is_some_and(is_break))
+            .map_or(0, |i| i + 1);
+        let end = (position..)
+            .find(|i| self.file_data.grapheme(*i).is_none_or(is_break))
+            .unwrap_or(position);
+        Some(start..end)
+    }
+    fn current_word(&self) -> Option<String> {
+        let word = self
+            .current_word_range()?
+            .filter_map(|i| self.file_data.grapheme(i))
+            .collect::<String>();
+        Some(
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_string(),
+        )
+    }
+    pub fn hint(&self) -> Option<&str> {
+        let word = self.current_word()?;
+        let glossary = &self.file_data.glossary;
+        glossary
+            .get(&word)
+            .or_else(|| glossary.get(&word.to_lowercase()))
+            .map(String::as_str)
+    }
+    pub fn save(&mut self) -> io::Result<()> {
+        self.file_data.progress.seconds += self.session.take_unsaved_seconds();
+        self.file_data.progress.section = self.file_data.section_at(self.position());
+        self.file_data.save()
+    }
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.file_data.progress.reset();
+        if self.file_data.freeform {
+            self.file_data.truncate(0);
+        }
+        self.clear_run();
+        self.save()
+    }
+    // Forget everything about the run so far, ready to start over
+    fn clear_run(&mut self) {
+        self.mistakes.clear();
+        self.first_tries.clear();
+        self.typed.clear();
+        self.attempt = None;
+        self.retry = None;
+        self.stuck = None;
+        self.skips.clear();
+        self.jumped.clear();
+        self.feedback = None;
+        self.deadline = None;
+        self.ghost_from = None;
+        self.run = ghost::Run::default();
+        self.completed = false;
+    }
+    pub fn end_session(&mut self) -> io::Result<()> {
+        // Keep a record of the fastest session on this story
+        let record = self
+            .session
+            .record_wpm()
+            .filter(|wpm| *wpm > self.file_data.progress.best_wpm);
+        if let Some(wpm) = record {
+            self.file_data.progress.best_wpm = wpm;
+        }
+        self.save()?;
+        // Keep the run around to race next time
+        if let Some(path) = &self.options.save_ghost
+            && !self.run.is_empty()
+        {
+            self.run.save(path)?;
+        }
+        self.file_data.log_session(&self.session, record.is_some())
+    }
+    // Finish the current story and start a new session on another
+    fn switch_to(&mut self, file_data: FileData) -> io::Result<()> {
+        self.end_session()?;
+        self.file_data = file_data;
+        self.clear_run();
+        self.session = Session::new(self.options.idle);
+        Ok(())
+    }
+    pub fn start_drill(&mut self, drill: String) -> io::Result<()> {
+        let file_data = FileData::drill(drill, &self.file_data.story_path);
+        self.switch_to(file_data)
+    }
+    pub fn open_story(&mut self, path: &Path) -> io::Result<()> {
+        let file_data = FileData::open(path, &self.options)?;
+        self.switch_to(file_data)
+    }
+    pub fn toggle_letters_only(&mut self) -> io::Result<()> {
+        // Reload the story so it's filtered with its own progress
+        let mut options = self.options.clone();
+        options.letters_only = !options.letters_only;
+        let file_data = FileData::open(&self.file_data.story_path, &options)?;
+        self.switch_to(file_data)?;
+        self.options = options;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use super::*;
+
+    #[test]
+    fn combining_marks_match_their_base() {
+        let story = "cafe\u{301}";
+        let graphemes = story.graphemes(true).collect::<Vec<_>>();
+        assert_eq!(graphemes.len(), 4);
+        assert_eq!(base_char(graphemes[3]), Some('e'));
+        assert!(grapheme_matches(graphemes[3], 'e', false));
+        assert!(grapheme_matches(graphemes[3], 'E', true));
+        assert!(!grapheme_matches(graphemes[3], 'a', false));
+        // Anything else stuck to the base can't be typed
+        assert_eq!(base_char("e\u{200d}x"), None);
+    }
+
+    #[test]
+    fn combining_marks_are_typed_with_their_base() {
+        let story = FileData::drill("cafe\u{301}".to_string(), Path::new(""));
+        let mut engine = Engine::new(story, Options::default()).unwrap();
+        assert!(!engine.untypeable());
+        for c in "cafe".chars() {
+            engine.advance_with(c);
+        }
+        assert!(engine.completed);
+        assert_eq!(engine.session.mistakes, 0);
+    }
+
+    #[test]
+    fn backspacing_over_a_jump_keeps_the_count() {
+        let story = FileData::drill("one two".to_string(), Path::new(""));
+        let mut engine = Engine::new(story, Options::default()).unwrap();
+        engine.advance_with('o');
+        engine.move_to(engine.next_word());
+        assert_eq!(engine.position(), 4);
+        for _ in 0..3 {
+            engine.backspace();
+        }
+        assert_eq!(engine.position(), 1);
+        assert_eq!(engine.session.correct, 1);
+    }
+
+    #[test]
+    fn an_indent_is_one_keystroke() {
+        let story = FileData::drill("a↩    b".to_string(), Path::new(""));
+        let mut engine = Engine::new(story, Options::default()).unwrap();
+        engine.advance_with('a');
+        engine.advance_with('↩');
+        engine.indent();
+        assert_eq!(engine.file_data.grapheme(engine.position()), Some("b"));
+        assert_eq!(engine.session.correct, 3);
+    }
+}
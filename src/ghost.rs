@@ -0,0 +1,44 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::path::Path;
+use std::time::Duration;
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+
+// A past run to race, as how far it had got at each keystroke
+#[derive(Serialize, Deserialize, Default)]
+pub struct Run {
+    // Characters past the start and the milliseconds it took to get there
+    keystrokes: Vec<(usize, u64)>,
+}
+
+impl Run {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let in_file =
+            |err: io::Error| io::Error::new(err.kind(), format!("{}: {err}", path.display()));
+        let file = fs::File::open(path).map_err(in_file)?;
+        serde_json::from_reader(file).map_err(|err| in_file(err.into()))
+    }
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string(self)?)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.keystrokes.is_empty()
+    }
+    pub fn record(&mut self, chars: usize, elapsed: Duration) {
+        // Only keystrokes that moved are worth keeping
+        if self.keystrokes.last().map(|(last, _)| *last) != Some(chars) {
+            self.keystrokes.push((chars, elapsed.as_millis() as u64));
+        }
+    }
+    pub fn chars_at(&self, elapsed: Duration) -> usize {
+        let millis = elapsed.as_millis() as u64;
+        let reached = self.keystrokes.partition_point(|(_, at)| *at <= millis);
+        reached
+            .checked_sub(1)
+            .map_or(0, |last| self.keystrokes[last].0)
+    }
+}
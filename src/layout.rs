@@ -0,0 +1,682 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::path::Path;
+use std::{fs, io};
+
+use serde::Deserialize;
+
+pub type Layer = &'static [&'static [char]];
+
+// The cur layer sits under the right hand
+pub const CUR_OFFSET: u8 = 6;
+
+pub enum Modifier {
+    Shift,
+    Sym,
+    Cur,
+}
+
+pub struct Location {
+    pub row: u8,
+    pub col: u8,
+    pub modifier: Option<Modifier>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Finger {
+    LeftPinky,
+    LeftRing,
+    LeftMiddle,
+    LeftIndex,
+    RightIndex,
+    RightMiddle,
+    RightRing,
+    RightPinky,
+}
+
+impl Finger {
+    pub const ALL: [Finger; 8] = [
+        Finger::LeftPinky,
+        Finger::LeftRing,
+        Finger::LeftMiddle,
+        Finger::LeftIndex,
+        Finger::RightIndex,
+        Finger::RightMiddle,
+        Finger::RightRing,
+        Finger::RightPinky,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Finger::LeftPinky => "Left Pinky",
+            Finger::LeftRing => "Left Ring",
+            Finger::LeftMiddle => "Left Middle",
+            Finger::LeftIndex => "Left Index",
+            Finger::RightIndex => "Right Index",
+            Finger::RightMiddle => "Right Middle",
+            Finger::RightRing => "Right Ring",
+            Finger::RightPinky => "Right Pinky",
+        }
+    }
+    pub fn is_left(self) -> bool {
+        matches!(
+            self,
+            Finger::LeftPinky | Finger::LeftRing | Finger::LeftMiddle | Finger::LeftIndex
+        )
+    }
+}
+
+pub struct Layout {
+    pub name: &'static str,
+    pub base: Layer,
+    pub sym: Layer,
+    pub cur: Layer,
+    // Unshifted and shifted symbol pairs, letters are uppercased
+    pub shift: &'static [(char, char)],
+    pub fingers: &'static [Finger],
+    // Key colors to tell the layout apart, if it has its own
+    pub colors: Option<KeyColors>,
+}
+
+// The base and hint key backgrounds as RGB
+#[derive(Clone, Copy, Deserialize)]
+pub struct KeyColors {
+    pub base: [u8; 3],
+    pub hint: [u8; 3],
+}
+
+// Every layout lights the next key in the same green
+const HINT: [u8; 3] = [48, 144, 48];
+
+impl Layout {
+    pub fn column_finger(&self, col: usize) -> Option<Finger> {
+        self.fingers.get(col).copied()
+    }
+    pub fn row_index(&self, location: &Location) -> Option<usize> {
+        // Layouts without a number row start from the top row
+        let offset = ROW_NAMES.len().checked_sub(self.base.len())?;
+        Some(location.row as usize + offset)
+    }
+    pub fn finger(&self, location: &Location) -> Finger {
+        // Anything past the last column is out under the right pinky
+        self.column_finger(location.col as usize)
+            .unwrap_or(Finger::RightPinky)
+    }
+    pub fn shift(&self, c: char) -> char {
+        self.shift
+            .iter()
+            .find(|(base, _)| *base == c)
+            .map_or(c.to_ascii_uppercase(), |(_, shifted)| *shifted)
+    }
+    pub fn location(&self, c: char) -> Option<Location> {
+        // Check the base layer
+        for (row_i, row) in self.base.iter().enumerate() {
+            for (col_i, c_candidate) in row.iter().enumerate() {
+                if *c_candidate == c {
+                    return Some(Location {
+                        row: row_i as u8,
+                        col: col_i as u8,
+                        modifier: None,
+                    });
+                }
+            }
+        }
+        // Check the sym layer
+        for (row_i, row) in self.sym.iter().enumerate() {
+            for (col_i, c_candidate) in row.iter().enumerate() {
+                if *c_candidate == c {
+                    return Some(Location {
+                        row: row_i as u8,
+                        col: col_i as u8,
+                        modifier: Some(Modifier::Sym),
+                    });
+                }
+            }
+        }
+        // Check the cur layer
+        for (row_i, row) in self.cur.iter().enumerate() {
+            for (col_i, c_candidate) in row.iter().enumerate() {
+                if *c_candidate == c {
+                    return Some(Location {
+                        row: row_i as u8,
+                        col: col_i as u8 + CUR_OFFSET,
+                        modifier: Some(Modifier::Cur),
+                    });
+                }
+            }
+        }
+        // Check the shifted base layer
+        for (row_i, row) in self.base.iter().enumerate() {
+            for (col_i, c_candidate) in row.iter().enumerate() {
+                if self.shift(*c_candidate) == c {
+                    return Some(Location {
+                        row: row_i as u8,
+                        col: col_i as u8,
+                        modifier: Some(Modifier::Shift),
+                    });
+                }
+            }
+        }
+        None
+    }
+    pub fn bigrams(&self, text: &str) -> Bigrams {
+        let mut bigrams = Bigrams::default();
+        let mut previous: Option<(char, Finger)> = None;
+        for c in text.chars() {
+            // Pairs are broken by anything off the layout, like spaces
+            let current = self.location(c).map(|location| (c, self.finger(&location)));
+            if let (Some((a, first)), Some((b, second))) = (previous, current) {
+                bigrams.total += 1;
+                // Repeating a key isn't a finger jump
+                if first == second && !a.eq_ignore_ascii_case(&b) {
+                    bigrams.same_finger += 1;
+                }
+                if first.is_left() != second.is_left() {
+                    bigrams.alternating += 1;
+                }
+            }
+            previous = current;
+        }
+        bigrams
+    }
+}
+
+#[derive(Default)]
+pub struct Bigrams {
+    pub total: usize,
+    pub same_finger: usize,
+    pub alternating: usize,
+}
+
+impl Bigrams {
+    fn percent(&self, count: usize) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        count as f64 / self.total as f64 * 100.0
+    }
+    pub fn same_finger_percent(&self) -> f64 {
+        self.percent(self.same_finger)
+    }
+    pub fn alternating_percent(&self) -> f64 {
+        self.percent(self.alternating)
+    }
+}
+
+const FINGERS_STAGGERED: &[Finger] = &[
+    Finger::LeftPinky,
+    Finger::LeftPinky,
+    Finger::LeftRing,
+    Finger::LeftMiddle,
+    Finger::LeftIndex,
+    Finger::LeftIndex,
+    Finger::RightIndex,
+    Finger::RightIndex,
+    Finger::RightMiddle,
+    Finger::RightRing,
+    Finger::RightPinky,
+    Finger::RightPinky,
+    Finger::RightPinky,
+    Finger::RightPinky,
+];
+
+const FINGERS_3L: &[Finger] = &[
+    Finger::LeftPinky,
+    Finger::LeftRing,
+    Finger::LeftMiddle,
+    Finger::LeftIndex,
+    Finger::LeftIndex,
+    Finger::RightIndex,
+    Finger::RightIndex,
+    Finger::RightMiddle,
+    Finger::RightRing,
+    Finger::RightPinky,
+];
+
+pub const ROW_NAMES: [&str; 4] = ["Number", "Top", "Home", "Bottom"];
+
+const SHIFT_US: &[(char, char)] = &[
+    ('`', '~'),
+    ('1', '!'),
+    ('2', '@'),
+    ('3', '#'),
+    ('4', '$'),
+    ('5', '%'),
+    ('6', '^'),
+    ('7', '&'),
+    ('8', '*'),
+    ('9', '('),
+    ('0', ')'),
+    ('[', '{'),
+    (']', '}'),
+    ('\'', '"'),
+    (',', '<'),
+    ('.', '>'),
+    ('/', '?'),
+    ('=', '+'),
+    ('\\', '|'),
+    ('-', '_'),
+    (';', ':'),
+];
+
+// Every layout in the order they're cycled through
+static LAYOUTS: [&Layout; 8] = [
+    &LAYOUT_QWERTY,
+    &LAYOUT_AZERTY,
+    &LAYOUT_DVORAK,
+    &LAYOUT_PROGRAMMER_DVORAK,
+    &LAYOUT_COLEMAK,
+    &LAYOUT_WORKMAN,
+    &LAYOUT_NORMAN,
+    &LAYOUT_3L,
+];
+
+pub fn all_layouts() -> &'static [&'static Layout] {
+    &LAYOUTS
+}
+
+// Layout files list each row as a string, with spaces for missing keys
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutFile {
+    name: String,
+    base: Vec<String>,
+    #[serde(default)]
+    sym: Vec<String>,
+    #[serde(default)]
+    cur: Vec<String>,
+    fingers: Option<Vec<Finger>>,
+    colors: Option<KeyColors>,
+}
+
+impl LayoutFile {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("layout needs a name".to_string());
+        }
+        if all_layouts().iter().any(|layout| layout.name == self.name) {
+            return Err(format!("{:?} is already a built-in layout", self.name));
+        }
+        if self.base.is_empty() || self.base.len() > ROW_NAMES.len() {
+            return Err(format!("base needs 1 to {} rows", ROW_NAMES.len()));
+        }
+        if self.base.iter().any(|row| row.trim().is_empty()) {
+            return Err("base rows can't be empty".to_string());
+        }
+        // The other layers are drawn over the base keys
+        for (layer, rows) in [("sym", &self.sym), ("cur", &self.cur)] {
+            if rows.len() > self.base.len() {
+                return Err(format!("{layer} has more rows than base"));
+            }
+        }
+        if self.fingers.as_ref().is_some_and(Vec::is_empty) {
+            return Err("fingers can't be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Custom layouts live for the rest of the program like the built-in ones
+fn leak_layer(rows: &[String]) -> Layer {
+    let rows = rows
+        .iter()
+        .map(|row| {
+            let keys = row.chars().map(|c| if c == ' ' { '\0' } else { c });
+            &*Vec::leak(keys.collect::<Vec<_>>())
+        })
+        .collect::<Vec<_>>();
+    Vec::leak(rows)
+}
+
+pub fn load(path: &Path) -> io::Result<&'static Layout> {
+    let invalid = |err: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {err}", path.display()),
+        )
+    };
+    let file = fs::File::open(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))?;
+    let file: LayoutFile = serde_json::from_reader(file).map_err(|err| invalid(err.to_string()))?;
+    file.validate().map_err(invalid)?;
+    // Guess the fingers from the width when they aren't given
+    let fingers = match file.fingers {
+        Some(fingers) => Vec::leak(fingers),
+        None if file.base.iter().all(|row| row.chars().count() <= 10) => FINGERS_3L,
+        None => FINGERS_STAGGERED,
+    };
+    Ok(Box::leak(Box::new(Layout {
+        name: String::leak(file.name),
+        base: leak_layer(&file.base),
+        sym: leak_layer(&file.sym),
+        cur: leak_layer(&file.cur),
+        shift: SHIFT_US,
+        fingers,
+        colors: file.colors,
+    })))
+}
+
+pub const LAYOUT_QWERTY: Layout = Layout {
+    name: "QWERTY",
+    base: KEYS_QWERTY_BASE,
+    sym: &[],
+    cur: &[],
+    shift: SHIFT_US,
+    fingers: FINGERS_STAGGERED,
+    colors: None,
+};
+
+const KEYS_QWERTY_BASE: &[&[char]] = &[
+    &[
+        '`', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '-', '=', '\0',
+    ],
+    &[
+        '\0', 'q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p', '[', ']', '\\',
+    ],
+    &[
+        '\0', 'a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', ';', '\'', '\0', '\0',
+    ],
+    &[
+        '\0', 'z', 'x', 'c', 'v', 'b', 'n', 'm', ',', '.', '/', '\0', '\0', '\0',
+    ],
+];
+
+// The digits sit on the shifted number row
+const SHIFT_AZERTY: &[(char, char)] = &[
+    ('&', '1'),
+    ('é', '2'),
+    ('"', '3'),
+    ('\'', '4'),
+    ('(', '5'),
+    ('-', '6'),
+    ('è', '7'),
+    ('_', '8'),
+    ('ç', '9'),
+    ('à', '0'),
+    (')', '°'),
+    ('=', '+'),
+    ('^', '¨'),
+    ('$', '£'),
+    ('ù', '%'),
+    ('*', 'µ'),
+    ('<', '>'),
+    (',', '?'),
+    (';', '.'),
+    (':', '/'),
+    ('!', '§'),
+];
+
+pub const LAYOUT_AZERTY: Layout = Layout {
+    name: "AZERTY",
+    base: KEYS_AZERTY_BASE,
+    sym: &[],
+    cur: &[],
+    shift: SHIFT_AZERTY,
+    fingers: FINGERS_STAGGERED,
+    colors: None,
+};
+
+const KEYS_AZERTY_BASE: &[&[char]] = &[
+    &[
+        '²', '&', 'é', '"', '\'', '(', '-', 'è', '_', 'ç', 'à', ')', '=', '\0',
+    ],
+    &[
+        '\0', 'a', 'z', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p', '^', '$', '\0',
+    ],
+    &[
+        '\0', 'q', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'm', 'ù', '*', '\0',
+    ],
+    &[
+        '<', 'w', 'x', 'c', 'v', 'b', 'n', ',', ';', ':', '!', '\0', '\0', '\0',
+    ],
+];
+
+pub const LAYOUT_DVORAK: Layout = Layout {
+    name: "Dvorak",
+    base: KEYS_DVORAK_BASE,
+    sym: &[],
+    cur: &[],
+    shift: SHIFT_US,
+    fingers: FINGERS_STAGGERED,
+    colors: Some(KeyColors {
+        base: [88, 64, 144],
+        hint: HINT,
+    }),
+};
+
+const KEYS_DVORAK_BASE: &[&[char]] = &[
+    &[
+        '`', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '[', ']', '\0',
+    ],
+    &[
+        '\0', '\'', ',', '.', 'p', 'y', 'f', 'g', 'c', 'r', 'l', '/', '=', '\\',
+    ],
+    &[
+        '\0', 'a', 'o', 'e', 'u', 'i', 'd', 'h', 't', 'n', 's', '-', '\0', '\0',
+    ],
+    &[
+        '\0', ';', 'q', 'j', 'k', 'x', 'b', 'm', 'w', 'v', 'z', '\0', '\0', '\0',
+    ],
+];
+
+// Symbols come unshifted and the digits need shift
+const SHIFT_PROGRAMMER_DVORAK: &[(char, char)] = &[
+    ('$', '~'),
+    ('&', '%'),
+    ('[', '7'),
+    ('{', '5'),
+    ('}', '3'),
+    ('(', '1'),
+    ('=', '9'),
+    ('*', '0'),
+    (')', '2'),
+    ('+', '4'),
+    (']', '6'),
+    ('!', '8'),
+    ('#', '`'),
+    (';', ':'),
+    (',', '<'),
+    ('.', '>'),
+    ('/', '?'),
+    ('@', '^'),
+    ('\\', '|'),
+    ('-', '_'),
+    ('\'', '"'),
+];
+
+pub const LAYOUT_PROGRAMMER_DVORAK: Layout = Layout {
+    name: "Programmer Dvorak",
+    base: KEYS_PROGRAMMER_DVORAK_BASE,
+    sym: &[],
+    cur: &[],
+    shift: SHIFT_PROGRAMMER_DVORAK,
+    fingers: FINGERS_STAGGERED,
+    colors: Some(KeyColors {
+        base: [88, 64, 144],
+        hint: HINT,
+    }),
+};
+
+const KEYS_PROGRAMMER_DVORAK_BASE: &[&[char]] = &[
+    &[
+        '$', '&', '[', '{', '}', '(', '=', '*', ')', '+', ']', '!', '#', '\0',
+    ],
+    &[
+        '\0', ';', ',', '.', 'p', 'y', 'f', 'g', 'c', 'r', 'l', '/', '@', '\\',
+    ],
+    &[
+        '\0', 'a', 'o', 'e', 'u', 'i', 'd', 'h', 't', 'n', 's', '-', '\0', '\0',
+    ],
+    &[
+        '\0', '\'', 'q', 'j', 'k', 'x', 'b', 'm', 'w', 'v', 'z', '\0', '\0', '\0',
+    ],
+];
+
+pub const LAYOUT_COLEMAK: Layout = Layout {
+    name: "Colemak",
+    base: KEYS_COLEMAK_BASE,
+    sym: &[],
+    cur: &[],
+    shift: SHIFT_US,
+    fingers: FINGERS_STAGGERED,
+    colors: Some(KeyColors {
+        base: [40, 104, 128],
+        hint: HINT,
+    }),
+};
+
+const KEYS_COLEMAK_BASE: &[&[char]] = &[
+    &[
+        '`', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '-', '=', '\0',
+    ],
+    &[
+        '\0', 'q', 'w', 'f', 'p', 'g', 'j', 'l', 'u', 'y', ';', '[', ']', '\\',
+    ],
+    &[
+        '\0', 'a', 'r', 's', 't', 'd', 'h', 'n', 'e', 'i', 'o', '\'', '\0', '\0',
+    ],
+    &[
+        '\0', 'z', 'x', 'c', 'v', 'b', 'k', 'm', ',', '.', '/', '\0', '\0', '\0',
+    ],
+];
+
+pub const LAYOUT_WORKMAN: Layout = Layout {
+    name: "Workman",
+    base: KEYS_WORKMAN_BASE,
+    sym: &[],
+    cur: &[],
+    shift: SHIFT_US,
+    fingers: FINGERS_STAGGERED,
+    colors: Some(KeyColors {
+        base: [112, 64, 120],
+        hint: HINT,
+    }),
+};
+
+const KEYS_WORKMAN_BASE: &[&[char]] = &[
+    &[
+        '`', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '-', '=', '\0',
+    ],
+    &[
+        '\0', 'q', 'd', 'r', 'w', 'b', 'j', 'f', 'u', 'p', ';', '[', ']', '\\',
+    ],
+    &[
+        '\0', 'a', 's', 'h', 't', 'g', 'y', 'n', 'e', 'o', 'i', '\'', '\0', '\0',
+    ],
+    &[
+        '\0', 'z', 'x', 'm', 'c', 'v', 'k', 'l', ',', '.', '/', '\0', '\0', '\0',
+    ],
+];
+
+pub const LAYOUT_NORMAN: Layout = Layout {
+    name: "Norman",
+    base: KEYS_NORMAN_BASE,
+    sym: &[],
+    cur: &[],
+    shift: SHIFT_US,
+    fingers: FINGERS_STAGGERED,
+    colors: Some(KeyColors {
+        base: [56, 96, 112],
+        hint: HINT,
+    }),
+};
+
+const KEYS_NORMAN_BASE: &[&[char]] = &[
+    &[
+        '`', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '-', '=', '\0',
+    ],
+    &[
+        '\0', 'q', 'w', 'd', 'f', 'k', 'j', 'u', 'r', 'l', ';', '[', ']', '\\',
+    ],
+    &[
+        '\0', 'a', 's', 'e', 't', 'g', 'y', 'n', 'i', 'o', 'h', '\'', '\0', '\0',
+    ],
+    &[
+        '\0', 'z', 'x', 'c', 'v', 'b', 'p', 'm', ',', '.', '/', '\0', '\0', '\0',
+    ],
+];
+
+pub const LAYOUT_3L: Layout = Layout {
+    name: "3l",
+    base: KEYS_3L_BASE,
+    sym: KEYS_3L_SYM,
+    cur: KEYS_3L_CUR,
+    shift: &[],
+    fingers: FINGERS_3L,
+    colors: Some(KeyColors {
+        base: [120, 80, 48],
+        hint: HINT,
+    }),
+};
+
+const KEYS_3L_BASE: &[&[char]] = &[
+    &['q', 'f', 'u', 'y', 'z', 'x', 'k', 'c', 'w', 'b'],
+    &['o', 'h', 'e', 'a', 'i', 'd', 'r', 't', 'n', 's'],
+    &[',', 'm', '.', 'j', ';', 'g', 'l', 'p', 'v', '\0'],
+];
+
+const KEYS_3L_SYM: &[&[char]] = &[
+    &['"', '_', '[', ']', '^', '!', '<', '>', '=', '&'],
+    &['/', '-', '{', '}', '*', '?', '(', ')', '\'', ':'],
+    &['#', '$', '|', '~', '`', '+', '%', '\\', '@'],
+];
+const KEYS_3L_CUR: &[&[char]] = &[
+    &['\0', '1', '2', '3'],
+    &['\0', '4', '5', '6'],
+    &['0', '7', '8', '9'],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_letter_is_typeable() {
+        for layout in [LAYOUT_WORKMAN, LAYOUT_NORMAN] {
+            for c in 'a'..='z' {
+                let location = layout.location(c);
+                assert!(
+                    location.is_some_and(|l| l.modifier.is_none()),
+                    "{c} is missing from {}",
+                    layout.name
+                );
+            }
+        }
+    }
+
+    fn at(layout: &Layout, c: char) -> (u8, u8, Option<&'static str>) {
+        let location = layout.location(c).unwrap();
+        let modifier = location.modifier.map(|m| match m {
+            Modifier::Shift => "shift",
+            Modifier::Sym => "sym",
+            Modifier::Cur => "cur",
+        });
+        (location.row, location.col, modifier)
+    }
+
+    #[test]
+    fn exclamation_mark() {
+        for layout in [
+            LAYOUT_QWERTY,
+            LAYOUT_DVORAK,
+            LAYOUT_COLEMAK,
+            LAYOUT_WORKMAN,
+            LAYOUT_NORMAN,
+        ] {
+            assert_eq!(at(&layout, '!'), (0, 1, Some("shift")), "{}", layout.name);
+        }
+        assert_eq!(at(&LAYOUT_AZERTY, '!'), (3, 10, None));
+        assert_eq!(at(&LAYOUT_PROGRAMMER_DVORAK, '!'), (0, 11, None));
+        assert_eq!(at(&LAYOUT_3L, '!'), (0, 5, Some("sym")));
+    }
+
+    #[test]
+    fn programmer_dvorak_symbols() {
+        let layout = LAYOUT_PROGRAMMER_DVORAK;
+        assert_eq!(at(&layout, '{'), (0, 3, None));
+        assert_eq!(at(&layout, '}'), (0, 4, None));
+        assert_eq!(at(&layout, ';'), (1, 1, None));
+        assert_eq!(at(&layout, '='), (0, 6, None));
+    }
+}
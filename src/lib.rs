@@ -0,0 +1,12 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+// The typing engine without the terminal, for embedding in other frontends
+pub mod bidi;
+pub mod cli;
+pub mod drill;
+pub mod engine;
+pub mod ghost;
+pub mod layout;
+pub mod session;
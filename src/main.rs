@@ -2,30 +2,189 @@
 //
 // SPDX-License-Identifier: GPL-3.0-only
 
-use std::io;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-mod cli;
+mod config;
+mod picker;
+mod shortcuts;
+mod summary;
 
-use cli::FileData;
+use clap::Parser;
+use config::{Config, Keys};
+use maddi_type::cli::{self, Cli, FileData};
+use maddi_type::engine::Engine;
+use maddi_type::layout::{
+    self, Bigrams, CUR_OFFSET, Finger, LAYOUT_QWERTY, Layer, Layout, Modifier,
+};
+use maddi_type::session::format_duration;
+use maddi_type::{bidi, drill};
+use picker::{Choice, LayoutChoice, LayoutMenu, Picker};
 use ratatui::{
     DefaultTerminal, Frame,
     buffer::Buffer,
-    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
-    layout::{Constraint, Layout as TuiLayout, Rect},
+    crossterm::{
+        event::{
+            self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+            PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+        },
+        execute, terminal,
+    },
+    layout::{Constraint, Flex, Layout as TuiLayout, Rect},
     style::{Color, Style, Stylize},
     symbols::border,
-    text::{Line, Text},
-    widgets::{Block, Paragraph, Widget},
+    text::{Line, Span},
+    widgets::{Block, Clear, LineGauge, Paragraph, Widget},
 };
+use shortcuts::Action;
+use summary::{Analysis, Completed, Help, Report, Summary};
 
 fn main() -> io::Result<()> {
-    let mut app = App::load();
-    let mut terminal = ratatui::init();
+    let cli = Cli::parse();
+    // Report on the story for scripts without taking over the terminal
+    if cli.stats
+        && let Some(story) = &cli.story
+    {
+        return match cli::stats(story, &cli.options()) {
+            Ok(stats) => {
+                println!("{stats:#}");
+                Ok(())
+            }
+            Err(err) => {
+                eprintln!("maddi-type: {err}");
+                std::process::exit(1);
+            }
+        };
+    }
+    // Piped stories need to be read before the terminal takes over
+    if cli.story.as_deref() == Some(Path::new("-"))
+        && let Err(err) = FileData::open(Path::new("-"), &cli.options())
+    {
+        eprintln!("maddi-type: {err}");
+        std::process::exit(1);
+    }
+    let mut terminal = match ratatui::try_init() {
+        Ok(terminal) => terminal,
+        Err(err) => {
+            eprintln!("maddi-type: couldn't open the terminal ({err})");
+            std::process::exit(1);
+        }
+    };
+    // Let the user choose a story when given a directory
+    let story = match &cli.story {
+        Some(story) if story.is_dir() => {
+            let chosen = Picker::scan(story).and_then(|picker| picker.choose(&mut terminal));
+            match chosen {
+                Ok(Some(story)) => Some(story),
+                result => {
+                    ratatui::restore();
+                    return result.map(|_| ());
+                }
+            }
+        }
+        story => story.clone(),
+    };
+    let mut app = match App::load(cli, story.as_deref()) {
+        Ok(app) => app,
+        Err(err) => {
+            ratatui::restore();
+            eprintln!("maddi-type: {err}");
+            std::process::exit(1);
+        }
+    };
+    // Ask for key release events so we can measure rollover
+    app.rollover.supported = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if app.rollover.supported {
+        execute!(
+            io::stdout(),
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        )?;
+    }
     let result = app.run(&mut terminal);
+    if app.rollover.supported {
+        execute!(io::stdout(), PopKeyboardEnhancementFlags)?;
+    }
     ratatui::restore();
     result
 }
 
+fn popup_area(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = TuiLayout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = TuiLayout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+// Keys have odd sizes so their labels sit in the middle, but never vanish
+fn odd_size(size: u16) -> u16 {
+    if size.is_multiple_of(2) {
+        size.saturating_sub(1).max(1)
+    } else {
+        size
+    }
+}
+
+fn ring_bell() {
+    // A missing bell isn't worth interrupting practice over
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+}
+
+// High contrast borders are thick and bright
+fn bordered(high_contrast: bool) -> Block<'static> {
+    match high_contrast {
+        true => Block::bordered().white().border_set(border::THICK),
+        false => Block::bordered().dark_gray().border_set(border::ROUNDED),
+    }
+}
+
+// Right to left rows hug the right edge
+fn aligned(spans: Vec<Span>, rtl: bool) -> Line {
+    match rtl {
+        true => Line::from(spans).right_aligned(),
+        false => Line::from(spans),
+    }
+}
+
+// A label and the key that does it, for the instruction bars
+fn instruction(keys: &Keys, label: &str, action: Action) -> [Span<'static>; 2] {
+    [
+        format!(" {label} ").into(),
+        format!("{} ", shortcuts::key_for(keys, action))
+            .blue()
+            .bold(),
+    ]
+}
+
+// As many instructions as fit in the width, most important first
+fn instructions(keys: &Keys, items: &[(&str, Action)], width: u16) -> Line<'static> {
+    let mut line = Line::default();
+    for &(label, action) in items {
+        let spans = instruction(keys, label, action);
+        if line.width() + spans.iter().map(Span::width).sum::<usize>() > width as usize {
+            break;
+        }
+        line.extend(spans);
+    }
+    line
+}
+
+fn render_message(message: Line, area: Rect, buf: &mut Buffer) {
+    let area = popup_area(area, message.width() as u16 + 2, 3);
+    Clear.render(area, buf);
+    Paragraph::new(message)
+        .block(Block::bordered().dark_gray().border_set(border::ROUNDED))
+        .render(area, buf);
+}
+
+#[derive(Clone, Copy)]
 struct Theme {
     text: Color,
     background: Color,
@@ -47,9 +206,108 @@ const THEME_KEY_HINT: Theme = Theme {
     shadow: Color::Rgb(32, 96, 32),
 };
 
+const THEME_KEY_UPCOMING: Theme = Theme {
+    text: Color::Rgb(16, 36, 32),
+    background: Color::Rgb(48, 108, 96),
+    highlight: Color::Rgb(64, 144, 128),
+    shadow: Color::Rgb(32, 72, 64),
+};
+
+// High contrast keys are black with the hint in bright blue
+const THEME_CONTRAST_BASE: Theme = Theme {
+    text: Color::White,
+    background: Color::Black,
+    highlight: Color::Black,
+    shadow: Color::Gray,
+};
+
+const THEME_CONTRAST_HINT: Theme = Theme {
+    text: Color::White,
+    background: Color::Rgb(0, 72, 232),
+    highlight: Color::Rgb(0, 72, 232),
+    shadow: Color::White,
+};
+
+const THEME_CONTRAST_UPCOMING: Theme = Theme {
+    text: Color::White,
+    background: Color::Rgb(72, 72, 72),
+    highlight: Color::Rgb(72, 72, 72),
+    shadow: Color::Gray,
+};
+
+const THEME_FINGER_PINKY: Theme = Theme {
+    text: Color::Rgb(32, 16, 48),
+    background: Color::Rgb(96, 56, 144),
+    highlight: Color::Rgb(128, 76, 192),
+    shadow: Color::Rgb(64, 38, 96),
+};
+
+const THEME_FINGER_RING: Theme = Theme {
+    text: Color::Rgb(12, 36, 40),
+    background: Color::Rgb(36, 108, 120),
+    highlight: Color::Rgb(48, 144, 160),
+    shadow: Color::Rgb(24, 72, 80),
+};
+
+const THEME_FINGER_MIDDLE: Theme = Theme {
+    text: Color::Rgb(48, 34, 12),
+    background: Color::Rgb(144, 104, 36),
+    highlight: Color::Rgb(192, 138, 48),
+    shadow: Color::Rgb(96, 70, 24),
+};
+
+const THEME_FINGER_INDEX: Theme = Theme {
+    text: Color::Rgb(48, 18, 26),
+    background: Color::Rgb(144, 56, 80),
+    highlight: Color::Rgb(192, 74, 106),
+    shadow: Color::Rgb(96, 38, 54),
+};
+
+const THEME_KEY_HOT: Theme = Theme {
+    text: Color::Rgb(48, 16, 12),
+    background: Color::Rgb(192, 48, 32),
+    highlight: Color::Rgb(240, 72, 48),
+    shadow: Color::Rgb(128, 32, 24),
+};
+
+// Shade a key from its background, the same way the built in themes are
+fn key_theme([r, g, b]: [u8; 3]) -> Theme {
+    let scale = |c: u8, by: u16| (c as u16 * by / 3).min(255) as u8;
+    let shade = |by| Color::Rgb(scale(r, by), scale(g, by), scale(b, by));
+    Theme {
+        text: shade(1),
+        background: Color::Rgb(r, g, b),
+        highlight: shade(4),
+        shadow: shade(2),
+    }
+}
+
+impl Theme {
+    fn blend(self, other: Theme, amount: f64) -> Theme {
+        let mix = |from: Color, to: Color| match (from, to) {
+            (Color::Rgb(r1, g1, b1), Color::Rgb(r2, g2, b2)) => {
+                let channel = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * amount) as u8;
+                Color::Rgb(channel(r1, r2), channel(g1, g2), channel(b1, b2))
+            }
+            // Named colors can't be mixed so pick the closest
+            _ if amount < 0.5 => from,
+            _ => to,
+        };
+        Theme {
+            text: mix(self.text, other.text),
+            background: mix(self.background, other.background),
+            highlight: mix(self.highlight, other.highlight),
+            shadow: mix(self.shadow, other.shadow),
+        }
+    }
+}
+
 struct Key {
-    theme: &'static Theme,
+    theme: Theme,
     text: Line<'static>,
+    shifted: char,
+    sym: char,
+    cur: char,
 }
 
 impl Widget for &Key {
@@ -86,96 +344,250 @@ impl Widget for &Key {
         let margin_x = area.width.saturating_sub(self.text.width() as u16) / 2;
         let margin_y = area.height.saturating_sub(1) / 2;
         buf.set_line(area.x + margin_x, area.y + margin_y, &self.text, area.width);
+        // Render the other layers in the corners
+        if area.width > 2 && area.height > 2 {
+            let style = Style::new().fg(Color::Gray);
+            let x = area.right() - 1;
+            if self.shifted != '\0' {
+                buf.set_string(area.x, area.y, self.shifted.to_string(), style);
+            }
+            if self.sym != '\0' {
+                buf.set_string(x, area.y, self.sym.to_string(), style);
+            }
+            if self.cur != '\0' {
+                buf.set_string(x, area.bottom() - 2, self.cur.to_string(), style);
+            }
+        }
     }
 }
 
 struct Keyboard {
+    config: &'static Config,
+    layouts: &'static [&'static Layout],
     layout: &'static Layout,
     keys: Vec<Vec<Key>>,
     draw: bool,
+    fingers: bool,
+    reference: bool,
+    heatmap: bool,
+    symbols: bool,
     sym: Key,
     cur: Key,
     shift: Key,
 }
 
-impl Default for Keyboard {
-    fn default() -> Self {
-        Keyboard::from_layout(&LAYOUT_QWERTY)
-    }
-}
-
 impl Keyboard {
     fn toggle_draw(&mut self) {
         self.draw = !self.draw;
     }
-    fn next_layout(&mut self) {
-        if std::ptr::eq(self.layout, &LAYOUT_QWERTY) {
-            self.set_dvorak();
-        } else if std::ptr::eq(self.layout, &LAYOUT_DVORAK) {
-            self.set_3l();
-        } else if std::ptr::eq(self.layout, &LAYOUT_3L) {
-            self.set_qwerty();
+    fn toggle_fingers(&mut self) {
+        self.fingers = !self.fingers;
+    }
+    fn toggle_reference(&mut self) {
+        self.reference = !self.reference;
+    }
+    fn toggle_heatmap(&mut self) {
+        self.heatmap = !self.heatmap;
+    }
+    fn toggle_symbols(&mut self) {
+        self.symbols = !self.symbols;
+    }
+    fn has_layers(&self) -> bool {
+        !self.layout.sym.is_empty() || !self.layout.cur.is_empty()
+    }
+    // The smallest each key can be drawn
+    fn min_key_size(&self) -> (u16, u16) {
+        match self.config.high_contrast {
+            true => HIGH_CONTRAST_KEY_SIZE,
+            false => (1, 1),
         }
     }
-    fn from_layout(layout: &'static Layout) -> Self {
+    // Enough room for every row of keys at their smallest and the modifiers
+    fn min_height(&self) -> u16 {
+        let (height, _) = self.min_key_size();
+        self.layout.base.len() as u16 * height + 3
+    }
+    fn next_layout(&self) -> &'static Layout {
+        let layouts = self.layouts;
+        let i = layouts
+            .iter()
+            .position(|layout| std::ptr::eq(*layout, self.layout))
+            .unwrap_or(0);
+        layouts[(i + 1) % layouts.len()]
+    }
+    fn from_name(name: &str, layouts: &'static [&'static Layout], config: &'static Config) -> Self {
+        let layout = layouts
+            .iter()
+            .find(|layout| layout.name == name)
+            .copied()
+            .unwrap_or(&LAYOUT_QWERTY);
+        Self::from_layout(layout, layouts, config)
+    }
+    fn from_layout(
+        layout: &'static Layout,
+        layouts: &'static [&'static Layout],
+        config: &'static Config,
+    ) -> Self {
         let mut keys = vec![];
         for row in layout.base {
             let mut row_keys = vec![];
             for key in *row {
-                let text = if *key == '\0' {
-                    Line::from("").centered()
-                } else {
-                    Line::from(key.to_string().bold().white()).centered()
-                };
                 row_keys.push(Key {
-                    theme: &THEME_KEY_BASE,
-                    text,
+                    theme: config.base,
+                    text: Self::label(*key),
+                    shifted: '\0',
+                    sym: '\0',
+                    cur: '\0',
                 })
             }
             keys.push(row_keys)
         }
         Self {
+            config,
             keys,
+            layouts,
             layout,
             draw: true,
+            fingers: false,
+            reference: false,
+            heatmap: false,
+            symbols: false,
             cur: Key {
-                theme: &THEME_KEY_BASE,
+                theme: config.base,
                 text: Line::from("cur".to_string().bold().white()).centered(),
+                shifted: '\0',
+                sym: '\0',
+                cur: '\0',
             },
             sym: Key {
-                theme: &THEME_KEY_BASE,
+                theme: config.base,
                 text: Line::from("sym".to_string().bold().white()).centered(),
+                shifted: '\0',
+                sym: '\0',
+                cur: '\0',
             },
             shift: Key {
-                theme: &THEME_KEY_BASE,
+                theme: config.base,
                 text: Line::from("shift".to_string().bold().white()).centered(),
+                shifted: '\0',
+                sym: '\0',
+                cur: '\0',
             },
         }
     }
-    fn set_qwerty(&mut self) {
-        *self = Self::from_layout(&LAYOUT_QWERTY)
+    fn label(c: char) -> Line<'static> {
+        if c == '\0' {
+            Line::from("").centered()
+        } else {
+            Line::from(c.to_string().bold().white()).centered()
+        }
+    }
+    fn layer_char(layer: Layer, row: usize, col: usize, offset: usize) -> char {
+        col.checked_sub(offset)
+            .and_then(|col| layer.get(row)?.get(col))
+            .copied()
+            .unwrap_or('\0')
+    }
+    fn show_layer(&mut self, layer: Layer, offset: usize) {
+        for (row_i, row) in self.keys.iter_mut().enumerate() {
+            for (col_i, key) in row.iter_mut().enumerate() {
+                key.text = Self::label(Self::layer_char(layer, row_i, col_i, offset));
+            }
+        }
     }
-    fn set_dvorak(&mut self) {
-        *self = Self::from_layout(&LAYOUT_DVORAK)
+    fn show_corners(&mut self, show: bool) {
+        let layout = self.layout;
+        for (row_i, row) in self.keys.iter_mut().enumerate() {
+            for (col_i, key) in row.iter_mut().enumerate() {
+                (key.sym, key.cur) = if show {
+                    (
+                        Self::layer_char(layout.sym, row_i, col_i, 0),
+                        Self::layer_char(layout.cur, row_i, col_i, CUR_OFFSET as usize),
+                    )
+                } else {
+                    ('\0', '\0')
+                };
+            }
+        }
     }
-    fn set_3l(&mut self) {
-        *self = Self::from_layout(&LAYOUT_3L)
+    fn show_symbols(&mut self, show: bool) {
+        let layout = self.layout;
+        for (row_i, row) in self.keys.iter_mut().enumerate() {
+            for (col_i, key) in row.iter_mut().enumerate() {
+                // Letters that are just uppercased aren't worth showing
+                let c = Self::layer_char(layout.base, row_i, col_i, 0);
+                let shifted = layout.shift(c);
+                let uppercased = c.is_alphabetic() && shifted == c.to_ascii_uppercase();
+                key.shifted = if show && !uppercased && shifted != c {
+                    shifted
+                } else {
+                    '\0'
+                };
+            }
+        }
+    }
+    fn set_layout(&mut self, layout: &'static Layout) {
+        // Keep the display toggles when switching layouts
+        let (draw, fingers, reference, heatmap, symbols) = (
+            self.draw,
+            self.fingers,
+            self.reference,
+            self.heatmap,
+            self.symbols,
+        );
+        *self = Self::from_layout(layout, self.layouts, self.config);
+        self.draw = draw;
+        self.fingers = fingers;
+        self.reference = reference;
+        self.heatmap = heatmap;
+        self.symbols = symbols;
     }
 
-    fn update(&mut self, c: char) {
-        for key in self.keys.iter_mut().flatten() {
-            key.theme = &THEME_KEY_BASE;
+    // With nothing left to type every highlight is cleared
+    fn update(&mut self, c: Option<char>, upcoming: &[char], misses: &BTreeMap<char, usize>) {
+        let (base, hint) = (self.base_theme(), self.hint_theme());
+        for row in &mut self.keys {
+            for (col_i, key) in row.iter_mut().enumerate() {
+                key.theme = match self.layout.column_finger(col_i) {
+                    Some(finger) if self.fingers => *finger_theme(finger),
+                    _ => base,
+                };
+            }
         }
         for modifier in [&mut self.sym, &mut self.cur, &mut self.shift] {
-            modifier.theme = &THEME_KEY_BASE;
+            modifier.theme = base;
+        }
+        if self.heatmap {
+            self.show_heatmap(misses);
+        }
+        // Faintly light the keys coming up after this one
+        for location in upcoming.iter().filter_map(|c| self.layout.location(*c)) {
+            if let Some(row) = self.keys.get_mut(location.row as usize)
+                && let Some(key) = row.get_mut(location.col as usize)
+            {
+                key.theme = self.config.upcoming
+            }
         }
-        let Some(location) = self.layout.location(c) else {
+        // The reference view shows every layer at once
+        let reference = self.reference && self.has_layers();
+        self.show_corners(reference);
+        self.show_symbols(self.symbols);
+        let Some(location) = c.and_then(|c| self.layout.location(c)) else {
+            self.show_layer(self.layout.base, 0);
             return;
         };
-        if let Some(row) = self.keys.get_mut(location.row as usize) {
-            if let Some(key) = row.get_mut(location.col as usize) {
-                key.theme = &THEME_KEY_HINT
+        // Show the labels for the layer the character lives on
+        match location.modifier {
+            Some(Modifier::Sym) if !reference => self.show_layer(self.layout.sym, 0),
+            Some(Modifier::Cur) if !reference => {
+                self.show_layer(self.layout.cur, CUR_OFFSET as usize)
             }
+            _ => self.show_layer(self.layout.base, 0),
+        }
+        if let Some(row) = self.keys.get_mut(location.row as usize)
+            && let Some(key) = row.get_mut(location.col as usize)
+        {
+            key.theme = hint
         }
         match location.modifier {
             Some(Modifier::Sym) => &mut self.sym,
@@ -183,7 +595,40 @@ impl Keyboard {
             Some(Modifier::Shift) => &mut self.shift,
             None => return,
         }
-        .theme = &THEME_KEY_HINT;
+        .theme = hint;
+    }
+    // The layout's own colors unless a theme has been chosen
+    fn base_theme(&self) -> Theme {
+        match self.layout.colors {
+            Some(colors) if !self.config.themed => key_theme(colors.base),
+            _ => self.config.base,
+        }
+    }
+    fn hint_theme(&self) -> Theme {
+        match self.layout.colors {
+            Some(colors) if !self.config.themed => key_theme(colors.hint),
+            _ => self.config.hint,
+        }
+    }
+    fn show_heatmap(&mut self, misses: &BTreeMap<char, usize>) {
+        // Total the misses for each key across all its layers
+        let mut heat = HashMap::<(u8, u8), usize>::new();
+        for (c, count) in misses {
+            if let Some(location) = self.layout.location(*c) {
+                *heat.entry((location.row, location.col)).or_default() += count;
+            }
+        }
+        let Some(max) = heat.values().copied().max() else {
+            return;
+        };
+        let base = self.base_theme();
+        for (row_i, row) in self.keys.iter_mut().enumerate() {
+            for (col_i, key) in row.iter_mut().enumerate() {
+                let count = heat.get(&(row_i as u8, col_i as u8)).copied();
+                let amount = count.unwrap_or(0) as f64 / max as f64;
+                key.theme = base.blend(THEME_KEY_HOT, amount);
+            }
+        }
     }
 }
 
@@ -194,26 +639,48 @@ impl Widget for &Keyboard {
     {
         // Render the surrounding block
         let title = Line::from(format!(" Layout - {} ", self.layout.name).bold());
-        let instructions = Line::from(vec![
-            " Toggle Hints ".into(),
-            "<C-h> ".blue().bold(),
-            " Next Layout ".into(),
-            "<C-n> ".blue().bold(),
-        ]);
-        let block = Block::bordered()
-            .dark_gray()
+        let keys = &self.config.keys;
+        let mut items = vec![
+            ("Hints", Action::ToggleHints),
+            ("Next Layout", Action::NextLayout),
+            ("Layouts", Action::Layouts),
+            ("Fingers", Action::Fingers),
+            ("Heatmap", Action::Heatmap),
+            ("Symbols", Action::Symbols),
+            ("Analysis", Action::Analysis),
+        ];
+        if self.has_layers() {
+            items.push(("Reference", Action::Reference));
+            items.push(("Layer Drill", Action::LayerDrill));
+        }
+        if self.heatmap {
+            items.push(("Clear Heatmap", Action::ClearHeatmap));
+        }
+        let instructions = instructions(keys, &items, block_area.width.saturating_sub(2));
+        let block = bordered(self.config.high_contrast)
             .title(title.centered())
-            .title_bottom(instructions.centered())
-            .border_set(border::ROUNDED);
+            .title_bottom(instructions.centered());
         let keyboard_area = block.inner(block_area);
         block.render(block_area, buf);
 
-        // Get the vertical layout for the keyboard
+        // Make sure there's room for at least one cell per key
         let rows_num = self.layout.base.len();
-        let mut row_height = keyboard_area.height / rows_num as u16;
-        if row_height % 2 == 0 {
-            row_height -= 1;
+        let cols_num = self.layout.base.iter().map(|row| row.len()).max().unwrap();
+        let row_height = keyboard_area.height.saturating_sub(1) / rows_num as u16;
+        let col_width = keyboard_area.width / cols_num as u16;
+        let (min_height, min_width) = self.min_key_size();
+        if row_height < min_height || col_width < min_width {
+            let [message_area] = TuiLayout::vertical([Constraint::Length(1)])
+                .flex(Flex::Center)
+                .areas(keyboard_area);
+            Paragraph::new("Too small for the keyboard".dark_gray())
+                .centered()
+                .render(message_area, buf);
+            return;
         }
+
+        // Get the vertical layout for the keyboard
+        let row_height = odd_size(row_height);
         let row_layout = {
             let mut constraints = vec![];
             constraints.push(Constraint::Fill(1));
@@ -227,11 +694,7 @@ impl Widget for &Keyboard {
         };
 
         // Get the horizontal layout
-        let cols_num = self.layout.base.iter().map(|row| row.len()).max().unwrap();
-        let mut col_width = keyboard_area.width / cols_num as u16;
-        if col_width % 2 == 0 {
-            col_width -= 1;
-        }
+        let col_width = odd_size(col_width);
         let col_constraints = {
             let mut constraints = vec![];
             constraints.push(Constraint::Fill(1));
@@ -249,156 +712,824 @@ impl Widget for &Keyboard {
             }
         }
         let modifier_row = row_areas.next().unwrap();
-        let cur_width = self.cur.text.width() + 2;
-        let sym_width = self.sym.text.width() + 2;
-        let shift_width = self.shift.text.width() + 2;
-        let [_, cur, sym, shift, _] = TuiLayout::horizontal([
-            Constraint::Fill(1),
-            Constraint::Max(cur_width as u16),
-            Constraint::Max(sym_width as u16),
-            Constraint::Max(shift_width as u16),
-            Constraint::Fill(1),
-        ])
-        .areas(*modifier_row);
-        self.cur.render(cur, buf);
-        self.sym.render(sym, buf);
-        self.shift.render(shift, buf);
+        // Layouts without layers only have shift to hold
+        let modifiers = match self.has_layers() {
+            true => vec![&self.cur, &self.sym, &self.shift],
+            false => vec![&self.shift],
+        };
+        // Abbreviate the modifier labels when they don't all fit
+        let label_width = |key: &Key| key.text.width() as u16 + 2;
+        let full_width = modifiers
+            .iter()
+            .map(|key| label_width(key))
+            .max()
+            .unwrap_or(0);
+        let abbreviate = full_width * modifiers.len() as u16 > modifier_row.width;
+        let modifiers = modifiers
+            .into_iter()
+            .map(|key| {
+                let label = key.text.to_string();
+                let label = match abbreviate {
+                    true => label.chars().take(2).collect(),
+                    false => label,
+                };
+                Key {
+                    theme: key.theme,
+                    text: Line::from(label.bold().white()).centered(),
+                    shifted: '\0',
+                    sym: '\0',
+                    cur: '\0',
+                }
+            })
+            .collect::<Vec<_>>();
+        // Give every modifier the same width and spread them out under the keys
+        let width = modifiers.iter().map(label_width).max().unwrap_or(0);
+        let [under_keys] = TuiLayout::horizontal([Constraint::Length(col_width * cols_num as u16)])
+            .flex(Flex::Center)
+            .areas(*modifier_row);
+        let areas = TuiLayout::horizontal(vec![Constraint::Length(width); modifiers.len()])
+            .flex(Flex::SpaceAround)
+            .split(under_keys);
+        for (key, area) in modifiers.iter().zip(areas.iter()) {
+            key.render(*area, buf);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Rollover {
+    supported: bool,
+    held: Vec<(KeyCode, Instant)>,
+    presses: usize,
+    overlapped: usize,
+    overlap: Duration,
+}
+
+impl Rollover {
+    fn press(&mut self, code: KeyCode) {
+        // Without release events every key looks held forever
+        if !self.supported {
+            return;
+        }
+        // A press while another key is still down is a rollover
+        if !self.held.is_empty() {
+            self.overlapped += 1;
+        }
+        self.presses += 1;
+        self.held.retain(|(held, _)| *held != code);
+        self.held.push((code, Instant::now()));
     }
+    fn release(&mut self, code: KeyCode) {
+        let Some(i) = self.held.iter().position(|(held, _)| *held == code) else {
+            return;
+        };
+        // The overlap lasts from the next key going down until this one comes up
+        if let Some((_, next_pressed)) = self.held.get(i + 1) {
+            self.overlap += next_pressed.elapsed();
+        }
+        self.held.remove(i);
+    }
+    fn percent(&self) -> f64 {
+        if self.presses == 0 {
+            return 0.0;
+        }
+        self.overlapped as f64 / self.presses as f64 * 100.0
+    }
+    fn average(&self) -> Duration {
+        if self.overlapped == 0 {
+            return Duration::ZERO;
+        }
+        self.overlap / self.overlapped as u32
+    }
+}
+
+// The widest the story text gets unless asked otherwise
+const STORY_WIDTH: u16 = 80;
+
+// Anything smaller gets a message instead of a mangled screen
+const MIN_WIDTH: u16 = 40;
+const MIN_HEIGHT: u16 = 15;
+// The story keeps a few lines however much room the keyboard wants
+const MIN_STORY_HEIGHT: u16 = 7;
+
+// High contrast keys are never shorter or narrower than this
+const HIGH_CONTRAST_KEY_SIZE: (u16, u16) = (3, 5);
+
+// Keys pressed just as time runs out shouldn't dismiss the results
+const TIME_UP_GRACE: Duration = Duration::from_secs(1);
+
+// The same key pressed again quicker than this is the key repeating
+const REPEAT_MIN: Duration = Duration::from_millis(50);
+
+const FLASH_LENGTH: Duration = Duration::from_millis(200);
+
+const BLINK: Duration = Duration::from_millis(500);
+
+const TICK: Duration = Duration::from_millis(250);
+
+// Timers only show whole seconds so there's no need to redraw more often
+const REDRAW: Duration = Duration::from_secs(1);
+
+const FRAME: Duration = Duration::from_millis(50);
+const CELEBRATION_LENGTH: Duration = Duration::from_millis(2500);
+
+struct Celebration {
+    frame: u64,
 }
 
-struct Error {
-    char: char,
-    position: usize,
+impl Widget for &Celebration {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        const GLYPHS: [&str; 5] = ["*", "+", "•", "✦", "·"];
+        const COLORS: [Color; 5] = [
+            Color::Rgb(230, 80, 80),
+            Color::Rgb(240, 200, 60),
+            Color::Rgb(80, 200, 100),
+            Color::Rgb(80, 150, 240),
+            Color::Rgb(200, 100, 220),
+        ];
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                // The confetti falls one row per frame
+                let seed = (x as u64)
+                    .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                    .wrapping_add((y as u64).wrapping_sub(self.frame))
+                    .wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                let seed = seed ^ (seed >> 31);
+                if !seed.is_multiple_of(17) {
+                    continue;
+                }
+                let glyph = GLYPHS[(seed >> 8) as usize % GLYPHS.len()];
+                let color = COLORS[(seed >> 16) as usize % COLORS.len()];
+                buf.set_string(x, y, glyph, Style::new().fg(color).bold());
+            }
+        }
+    }
 }
 
 struct App {
+    cli: Cli,
+    config: &'static Config,
     keyboard: Keyboard,
-    file_data: FileData,
-    errors: Vec<Error>,
+    engine: Engine,
+    rollover: Rollover,
+    animations: bool,
+    celebration: Option<Instant>,
+    blink_from: Instant,
+    last_typed: Option<(char, Instant)>,
+    goal_met_at: Option<Instant>,
+    picker: Option<Picker>,
+    layout_menu: Option<LayoutMenu>,
+    confirm_reset: bool,
+    summary: bool,
+    // How far down a popup too tall for the screen has been scrolled
+    scroll: Cell<u16>,
+    // Worked out when the popup opens since the story doesn't change under it
+    analysis: Option<Vec<(&'static Layout, Bigrams)>>,
+    help: bool,
+    too_small: bool,
+    // The keys missed last time, shown before practice starts
+    report: bool,
+    // Whether the screen is out of date, and when and how it was last drawn
+    dirty: bool,
+    drawn: (Instant, bool),
+    saved_at: Instant,
     exit: bool,
 }
 
 impl App {
-    fn position(&self) -> usize {
-        self.file_data.progress.chars
+    fn celebrating(&self) -> bool {
+        self.celebration
+            .is_some_and(|start| start.elapsed() < CELEBRATION_LENGTH)
     }
-    fn position_mut(&mut self) -> &mut usize {
-        &mut self.file_data.progress.chars
+    // `?` is easier to find than F1, but only while it can't be story text
+    fn question_mark_helps(&self) -> bool {
+        let file_data = &self.engine.file_data;
+        !file_data.freeform && !file_data.story.contains('?')
     }
-    fn next(&self) -> Option<char> {
-        self.file_data
-            .story
-            .chars()
-            .nth(self.file_data.progress.chars)
+    fn styled(&self, i: usize, word: Option<&Range<usize>>) -> Span<'_> {
+        let span = self.styled_grapheme(i, word.is_some_and(|word| word.contains(&i)));
+        // Mark where typing at the target speed would have reached
+        match self.engine.ghost() {
+            Some(ghost) if ghost == i && i != self.engine.position() => {
+                span.bg(Color::Rgb(72, 48, 112))
+            }
+            _ => span,
+        }
     }
-    fn advance(&mut self) {
-        if self.next().is_none() {
-            return;
+    fn styled_grapheme(&self, i: usize, in_word: bool) -> Span<'_> {
+        let engine = &self.engine;
+        let g = engine.file_data.grapheme(i).unwrap_or_default();
+        // Show spaces right around the cursor so they aren't mistaken for nothing
+        let g = match g == " " && i.abs_diff(engine.position()) <= 1 {
+            true => "·",
+            false => g,
+        };
+        // Line breaks and indents stand out from the text
+        let marker = g == "↩" || g == "⇥";
+        if i == engine.position() {
+            let span = match self.cursor_visible() {
+                true => g.bold().underlined(),
+                false => g.into(),
+            };
+            let span = if marker { span.cyan() } else { span.white() };
+            // Keep a missed character lit until it's typed right
+            return match engine.stuck == Some(i) {
+                true => span.bold().bg(Color::Rgb(96, 24, 24)),
+                false => span,
+            };
+        }
+        if i > engine.position() {
+            // The rest of the current word stands out to read ahead
+            return if marker {
+                g.cyan()
+            } else if in_word {
+                g.white().bold()
+            } else if self.config.high_contrast {
+                g.white()
+            } else {
+                g.gray()
+            };
+        };
+        if engine.skips.contains(&i) {
+            return g.yellow().crossed_out();
+        }
+        let span = engine
+            .mistakes
+            .iter()
+            .find(|e| e.position == i)
+            .map(|e| e.char.to_string().red().underlined())
+            .unwrap_or(match engine.first_tries.get(i).copied().flatten() {
+                Some(true) => g.green(),
+                Some(false) => g.red(),
+                None if self.config.high_contrast => g.gray(),
+                None => g.dark_gray(),
+            });
+        let span = if in_word { span.bold() } else { span };
+        // Briefly flash the character that was just typed
+        match self.flash() {
+            Some(true) if i + 1 == engine.position() => span.bg(Color::Rgb(24, 72, 24)),
+            Some(false) if i + 1 == engine.position() => span.bg(Color::Rgb(96, 24, 24)),
+            _ => span,
         }
-        *self.position_mut() += 1
     }
-    fn advance_with(&mut self, c: char) {
-        if self.next().is_none() {
-            return;
+    // What was typed at a position, lined up under the story
+    fn typed_span(&self, i: usize) -> Span<'_> {
+        let engine = &self.engine;
+        let g = engine.file_data.grapheme(i).unwrap_or_default();
+        let blank = " ".repeat(Span::raw(g).width());
+        let Some(c) = engine
+            .typed
+            .get(i)
+            .copied()
+            .flatten()
+            .filter(|_| i < engine.position())
+        else {
+            return blank.into();
+        };
+        if engine.matches(g, c) {
+            return Span::raw(g).green();
         }
-        if self.next() != Some(c) {
-            self.errors.push(Error {
-                char: c,
-                position: self.position(),
-            })
+        // Make a stray space visible
+        match c {
+            ' ' => "·".red(),
+            c => c.to_string().red().bold(),
         }
-        self.advance();
     }
-    fn backspace(&mut self) {
-        *self.position_mut() = self.position().saturating_sub(1);
-        let position = self.position();
-        self.errors.retain(|e| e.position != position);
+    fn cursor_visible(&self) -> bool {
+        // Stay solid while typing and blink once idle
+        let blinks = self.blink_from.elapsed().as_millis() / BLINK.as_millis();
+        !self.config.blink || blinks.is_multiple_of(2)
     }
-    fn load() -> Self {
-        Self {
-            keyboard: Keyboard::default(),
-            file_data: FileData::load().unwrap(),
-            errors: vec![],
+    fn flash(&self) -> Option<bool> {
+        self.engine
+            .feedback
+            .filter(|(_, start)| start.elapsed() < FLASH_LENGTH)
+            .map(|(correct, _)| correct)
+    }
+    fn story_rows(&self, width: u16, height: u16) -> (Vec<Vec<usize>>, usize) {
+        let file_data = &self.engine.file_data;
+        let width = width.max(1) as usize;
+        let position = self.engine.position();
+        // Only look a few screens back, snapped so the wrapping holds
+        // steady as the cursor moves
+        let limit = width * height.max(1) as usize * 2;
+        let floor = position.saturating_sub(limit) / limit * limit;
+        // Wrap from the start of a line far enough back to fill the view,
+        // or from a word if there's no line that close
+        let start = (floor..position)
+            .rev()
+            .filter(|i| file_data.grapheme(*i) == Some("↩"))
+            .nth(height as usize / 2)
+            .or_else(|| {
+                (floor..position)
+                    .find(|i| file_data.grapheme(*i) == Some(" "))
+                    .filter(|_| floor > 0)
+            })
+            .map_or(floor, |i| i + 1);
+        let mut rows = vec![vec![]];
+        let mut rows_before = 0;
+        for i in start.. {
+            // Stop once there's enough below the current row
+            if i > position && rows.len() > rows_before + height as usize {
+                break;
+            }
+            let Some(g) = file_data.grapheme(i) else {
+                break;
+            };
+            let row = rows.last_mut().unwrap();
+            let row_width = row
+                .iter()
+                .filter_map(|i| file_data.grapheme(*i))
+                .map(|g| Span::raw(g).width())
+                .sum::<usize>();
+            // Wrap at the last space, or mid word if there isn't one
+            if row_width + Span::raw(g).width() > width {
+                let split = row
+                    .iter()
+                    .rposition(|i| file_data.grapheme(*i) == Some(" "))
+                    .map_or(row.len(), |space| space + 1);
+                let wrapped = row.split_off(split);
+                rows.push(wrapped);
+            }
+            if i == position {
+                rows_before = rows.len();
+            }
+            rows.last_mut().unwrap().push(i);
+            if g == "↩" {
+                rows.push(vec![]);
+            }
+        }
+        // The cursor sits past the end once the story is complete
+        let current_row = rows
+            .iter()
+            .position(|row| row.contains(&position))
+            .unwrap_or(rows.len() - 1);
+        (rows, current_row)
+    }
+    fn load(cli: Cli, story: Option<&Path>) -> io::Result<Self> {
+        // The config lives for the rest of the program
+        let mut config = Config::load()?;
+        if cli.high_contrast {
+            config.use_high_contrast();
+        }
+        let config: &'static Config = Box::leak(Box::new(config));
+        let mut options = cli.options();
+        options.idle = config.idle;
+        let mut engine = Engine::load(story, options)?;
+        // Add the custom layout to the cycle and start on it
+        let mut keyboard = match &cli.layout_file {
+            Some(path) => {
+                let custom = layout::load(path)?;
+                let mut layouts = layout::all_layouts().to_vec();
+                layouts.push(custom);
+                Keyboard::from_layout(custom, Vec::leak(layouts), config)
+            }
+            None => Keyboard::from_name(
+                &engine.file_data.progress.layout,
+                layout::all_layouts(),
+                config,
+            ),
+        };
+        keyboard.draw = !cli.no_keyboard;
+        engine.layout = keyboard.layout;
+        let report = cli.report && !engine.file_data.progress.misses.is_empty();
+        Ok(Self {
+            config,
+            keyboard,
+            engine,
+            rollover: Rollover::default(),
+            animations: !cli.no_animations,
+            celebration: None,
+            blink_from: Instant::now(),
+            last_typed: None,
+            goal_met_at: None,
+            picker: None,
+            layout_menu: None,
+            confirm_reset: false,
+            summary: false,
+            scroll: Cell::new(0),
+            analysis: None,
+            help: false,
+            too_small: false,
+            report,
+            dirty: true,
+            saved_at: Instant::now(),
+            drawn: (Instant::now(), false),
             exit: false,
+            cli,
+        })
+    }
+    fn open_picker(&mut self) -> io::Result<()> {
+        let story_path = &self.engine.file_data.story_path;
+        let dir = match story_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut picker = Picker::scan(dir)?;
+        // Start on the story that's already open
+        picker.selected = picker
+            .files
+            .iter()
+            .position(|path| path.file_name() == story_path.file_name())
+            .unwrap_or(0);
+        self.picker = Some(picker);
+        Ok(())
+    }
+    fn use_layout(&mut self, layout: &'static Layout) {
+        self.keyboard.set_layout(layout);
+        self.engine.layout = layout;
+        self.engine.file_data.progress.layout = layout.name.to_string();
+    }
+    fn reset(&mut self) -> io::Result<()> {
+        self.celebration = None;
+        self.goal_met_at = None;
+        self.engine.reset()
+    }
+    fn start_drill(&mut self, drill: String) -> io::Result<()> {
+        // Finish off the story before drilling
+        self.engine.start_drill(drill)?;
+        self.celebration = None;
+        self.goal_met_at = None;
+        self.summary = false;
+        self.scroll.set(0);
+        Ok(())
+    }
+    fn toggle_letters_only(&mut self) {
+        // Stay as we were if the story can't be reloaded
+        match self.engine.toggle_letters_only() {
+            Ok(()) => self.opened(),
+            Err(err) => self.engine.file_data.warning = Some(err.to_string()),
+        }
+    }
+    fn open_story(&mut self, path: &Path) {
+        // Stay on the current story if the new one can't be used
+        match self.engine.open_story(path) {
+            Ok(()) => self.opened(),
+            Err(err) => self.engine.file_data.warning = Some(err.to_string()),
         }
     }
+    // Pick up the layout a newly opened story was last typed on
+    fn opened(&mut self) {
+        let draw = self.keyboard.draw;
+        let layouts = self.keyboard.layouts;
+        let name = &self.engine.file_data.progress.layout;
+        self.keyboard = Keyboard::from_name(name, layouts, self.config);
+        self.keyboard.draw = draw;
+        self.engine.layout = self.keyboard.layout;
+        self.celebration = None;
+        self.goal_met_at = None;
+    }
 
     fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
         while !self.exit {
-            terminal.draw(|frame| self.draw(frame))?;
+            if self.needs_redraw() {
+                terminal.draw(|frame| self.draw(frame))?;
+                self.dirty = false;
+                self.drawn = (Instant::now(), self.cursor_visible());
+            }
             self.handle_events()?;
+            // Save along the way so a killed terminal doesn't lose the session
+            if let Some(interval) = self.config.autosave
+                && self.saved_at.elapsed() >= interval
+            {
+                self.engine.save()?;
+                self.saved_at = Instant::now();
+            }
         }
-        self.file_data.save().unwrap();
-        Ok(())
+        self.engine.end_session()
+    }
+
+    fn needs_redraw(&self) -> bool {
+        // Only draw when something changed or a timer has moved on
+        let (at, cursor) = self.drawn;
+        self.dirty
+            || self.celebration.is_some()
+            || self.flash().is_some()
+            || self.cursor_visible() != cursor
+            || at.elapsed() >= REDRAW
     }
 
     fn draw(&mut self, frame: &mut Frame) {
+        // Wait for the window to grow before drawing anything else
+        let area = frame.area();
+        self.too_small = area.width < MIN_WIDTH || area.height < MIN_HEIGHT;
+        if self.too_small {
+            let message = format!("Too small, needs {MIN_WIDTH}x{MIN_HEIGHT}");
+            let [area] = TuiLayout::vertical([Constraint::Length(1)])
+                .flex(Flex::Center)
+                .areas(area);
+            frame.render_widget(Paragraph::new(message.yellow()).centered(), area);
+            return;
+        }
         if self.keyboard.draw {
-            let vertical = TuiLayout::vertical([Constraint::Fill(2), Constraint::Fill(1)]);
+            // Give the keys more room when they need to be easy to see
+            let vertical = match self.config.high_contrast {
+                true => {
+                    let height = (area.height / 2).max(self.keyboard.min_height());
+                    TuiLayout::vertical([
+                        Constraint::Min(MIN_STORY_HEIGHT),
+                        Constraint::Length(height),
+                    ])
+                }
+                false => TuiLayout::vertical([Constraint::Fill(2), Constraint::Fill(1)]),
+            };
             let [app, keyboard] = vertical.areas(frame.area());
             // Update the highlighted block for the keyboard
-            if let Some(c) = self.next() {
-                self.keyboard.update(c);
+            let engine = &self.engine;
+            let next = match engine.file_data.freeform {
+                // There's nothing to come so light up the last key instead
+                true => engine.position().saturating_sub(1),
+                false => engine.position(),
+            };
+            let chars = (next..=next + self.cli.peek)
+                .filter_map(|i| engine.file_data.grapheme(i)?.chars().next())
+                // Point at the unshifted key when case doesn't matter
+                .map(|c| match engine.options.ignore_case {
+                    true => c.to_lowercase().next().unwrap_or(c),
+                    false => c,
+                })
+                .collect::<Vec<_>>();
+            let (c, upcoming) = match chars.split_first() {
+                Some((c, upcoming)) => (Some(*c), upcoming),
+                None => (None, &[][..]),
+            };
+            let misses = &engine.file_data.progress.misses;
+            self.keyboard.update(c, upcoming, misses);
+            // The report lights up the heatmap underneath it
+            if self.report {
+                self.keyboard.show_heatmap(misses);
             }
             frame.render_widget(&self.keyboard, keyboard);
             frame.render_widget(&*self, app);
         } else {
             frame.render_widget(&*self, frame.area());
         }
+        // Draw the confetti over everything
+        if let Some(start) = self.celebration.filter(|_| self.celebrating()) {
+            let frame_number = (start.elapsed().as_millis() / FRAME.as_millis()) as u64;
+            let celebration = Celebration {
+                frame: frame_number,
+            };
+            frame.render_widget(&celebration, frame.area());
+        }
+        if let Some(menu) = &self.layout_menu {
+            frame.render_widget(menu, frame.area());
+        }
+        if let Some(picker) = &self.picker {
+            frame.render_widget(picker, frame.area());
+        }
+        if self.engine.completed && !self.celebrating() && !self.summary {
+            frame.render_widget(Completed { app: self }, frame.area());
+        }
+        if let Some(bigrams) = &self.analysis {
+            frame.render_widget(Analysis { app: self, bigrams }, frame.area());
+        }
+        if self.report {
+            frame.render_widget(Report { app: self }, frame.area());
+        }
+        if self.help {
+            frame.render_widget(Help { app: self }, frame.area());
+        }
+        if self.summary {
+            frame.render_widget(Summary { app: self }, frame.area());
+        }
     }
 
     fn handle_events(&mut self) -> io::Result<()> {
-        match event::read()? {
+        // Keep redrawing while the celebration plays
+        if self.celebration.is_some() {
+            if !self.celebrating() {
+                self.celebration = None;
+                return Ok(());
+            }
+            if !event::poll(FRAME)? {
+                return Ok(());
+            }
+            // Any key skips the celebration
+            if let Event::Key(key_event) = self.read_event()?
+                && key_event.kind == KeyEventKind::Press
+            {
+                self.celebration = None;
+            }
+            return Ok(());
+        }
+        // Show the results once time runs out or the goal is met
+        if self.engine.time_up() && !self.summary {
+            self.summary = true;
+            self.dirty = true;
+            return Ok(());
+        }
+        if self.engine.goal_met() && !self.summary {
+            self.goal_met_at = Some(Instant::now());
+            self.summary = true;
+            self.dirty = true;
+            return Ok(());
+        }
+        // Redraw now and then so the timers tick between keystrokes
+        let timeout = if self.flash().is_some() { FRAME } else { TICK };
+        if !event::poll(timeout)? {
+            return Ok(());
+        }
+        // Keys and resizes all need showing
+        self.dirty = true;
+        match self.read_event()? {
+            // Only leaving works until there's room to see the story
+            Event::Key(key_event)
+                if self.too_small && self.config.keys.exit.matches(&key_event) =>
+            {
+                self.exit()
+            }
+            Event::Key(_) if self.too_small => {}
             Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                self.handle_key_event(key_event)
+                self.rollover.press(key_event.code);
+                // Any key but the drill key leaves from the summary
+                if self.summary {
+                    let grace = (self.engine.time_up()
+                        && self
+                            .engine
+                            .deadline
+                            .is_some_and(|deadline| deadline.elapsed() < TIME_UP_GRACE))
+                        || self
+                            .goal_met_at
+                            .is_some_and(|met| met.elapsed() < TIME_UP_GRACE);
+                    match key_event.code {
+                        _ if grace => {}
+                        KeyCode::Up => self.scroll.set(self.scroll.get().saturating_sub(1)),
+                        KeyCode::Down => self.scroll.set(self.scroll.get() + 1),
+                        KeyCode::Char('d') => {
+                            let misses = &self.engine.file_data.progress.misses;
+                            self.start_drill(drill::generate(misses, self.cli.drill_length))?
+                        }
+                        _ => self.exit(),
+                    }
+                } else if self.engine.completed {
+                    // Offer to go again or leave once the story is done
+                    self.engine.completed = false;
+                    match key_event.code {
+                        KeyCode::Char('r') => self.reset()?,
+                        _ if self.config.keys.exit.matches(&key_event) => self.summary = true,
+                        _ => {}
+                    }
+                } else if self.analysis.is_some() {
+                    self.analysis = None;
+                } else if self.report {
+                    self.report = false;
+                } else if self.help {
+                    match key_event.code {
+                        KeyCode::Up => self.scroll.set(self.scroll.get().saturating_sub(1)),
+                        KeyCode::Down => self.scroll.set(self.scroll.get() + 1),
+                        _ => {
+                            self.help = false;
+                            self.scroll.set(0);
+                        }
+                    }
+                } else if self.confirm_reset {
+                    self.confirm_reset = false;
+                    if key_event.code == KeyCode::Char('y') {
+                        self.reset()?;
+                    }
+                } else if self.picker.is_some() {
+                    self.handle_picker_key_event(key_event);
+                } else if self.layout_menu.is_some() {
+                    self.handle_layout_menu_key_event(key_event);
+                } else {
+                    let mistakes = self.engine.session.mistakes;
+                    self.handle_key_event(key_event)?;
+                    self.engine.record_run();
+                    if self.cli.bell && self.engine.session.mistakes > mistakes {
+                        ring_bell();
+                    }
+                    // Celebrate finishing the story
+                    if self.engine.completed && self.animations && self.celebration.is_none() {
+                        self.celebration = Some(Instant::now());
+                    }
+                }
             }
             _ => {}
         };
         Ok(())
     }
 
-    fn handle_key_event(&mut self, key_event: KeyEvent) {
-        match key_event {
-            KeyEvent {
-                code: KeyCode::Esc, ..
-            } => self.exit(),
-            KeyEvent {
-                code: KeyCode::Char('n'),
-                modifiers,
-                ..
-            } if modifiers.contains(KeyModifiers::CONTROL) => self.keyboard.next_layout(),
-            KeyEvent {
-                code: KeyCode::Char('h'),
-                modifiers,
-                ..
-            } if modifiers.contains(KeyModifiers::CONTROL) => self.keyboard.toggle_draw(),
-            KeyEvent {
-                code: KeyCode::Char(char),
-                ..
-            } => {
-                self.advance_with(char);
-            }
-            KeyEvent {
-                code: KeyCode::Enter,
-                ..
-            } => {
-                self.advance_with('↩');
-            }
-            KeyEvent {
-                code: KeyCode::Tab, ..
-            } => {
-                self.advance();
-            }
-            KeyEvent {
-                code: KeyCode::Backspace,
-                ..
-            } => {
-                self.backspace();
+    // Keys come up whatever is showing, so let go of them straight away
+    fn read_event(&mut self) -> io::Result<Event> {
+        let event = event::read()?;
+        if let Event::Key(key_event) = event
+            && key_event.kind == KeyEventKind::Release
+        {
+            self.rollover.release(key_event.code);
+        }
+        Ok(event)
+    }
+
+    fn handle_picker_key_event(&mut self, key_event: KeyEvent) {
+        let Some(picker) = &mut self.picker else {
+            return;
+        };
+        match picker.handle_key(key_event.code) {
+            Some(Choice::Open(path)) => {
+                self.picker = None;
+                self.open_story(&path);
+            }
+            Some(Choice::Cancel) => self.picker = None,
+            None => {}
+        }
+    }
+
+    fn handle_layout_menu_key_event(&mut self, key_event: KeyEvent) {
+        let Some(menu) = &mut self.layout_menu else {
+            return;
+        };
+        match menu.handle_key(key_event.code) {
+            Some(LayoutChoice::Use(layout)) => {
+                self.layout_menu = None;
+                self.use_layout(layout);
             }
-            _ => {}
+            Some(LayoutChoice::Cancel) => self.layout_menu = None,
+            None => {}
         }
     }
 
+    fn repeated(&mut self, c: char) -> bool {
+        let last = self.last_typed.replace((c, Instant::now()));
+        // Terminals that report repeats separately never send them as presses
+        !self.rollover.supported
+            && last.is_some_and(|(last, at)| last == c && at.elapsed() < REPEAT_MIN)
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent) -> io::Result<()> {
+        self.blink_from = Instant::now();
+        // Any key resumes a paused session
+        if self.engine.session.paused {
+            self.engine.session.resume();
+            return Ok(());
+        }
+        let Some(action) = shortcuts::action(&self.config.keys, &key_event) else {
+            // Everything else without control is typed
+            if let KeyCode::Char(c) = key_event.code
+                && !key_event.modifiers.contains(KeyModifiers::CONTROL)
+                && !self.repeated(c)
+            {
+                match c {
+                    '?' if self.question_mark_helps() => self.help = true,
+                    c => self.engine.advance_with(c),
+                }
+            }
+            return Ok(());
+        };
+        let engine = &mut self.engine;
+        match action {
+            Action::Exit => self.summary = true,
+            Action::NextLayout => self.use_layout(self.keyboard.next_layout()),
+            Action::ToggleHints => self.keyboard.toggle_draw(),
+            Action::Help => self.help = true,
+            Action::Reset => self.confirm_reset = true,
+            Action::Skip => engine.skip(),
+            Action::Pause => engine.session.pause(),
+            Action::Open => self.open_picker()?,
+            Action::LettersOnly if !engine.file_data.freeform => self.toggle_letters_only(),
+            Action::Fingers => self.keyboard.toggle_fingers(),
+            Action::Reference => self.keyboard.toggle_reference(),
+            Action::Symbols => self.keyboard.toggle_symbols(),
+            Action::Heatmap => self.keyboard.toggle_heatmap(),
+            Action::ClearHeatmap if self.keyboard.heatmap => {
+                engine.file_data.progress.misses.clear()
+            }
+            Action::Analysis => {
+                // Compare every layout on the same text
+                let story = &engine.file_data.story;
+                let bigrams = self
+                    .keyboard
+                    .layouts
+                    .iter()
+                    .map(|layout| (*layout, layout.bigrams(story)))
+                    .collect();
+                self.analysis = Some(bigrams);
+            }
+            Action::Layouts => {
+                let menu = LayoutMenu::new(self.keyboard.layouts, self.keyboard.layout);
+                self.layout_menu = Some(menu);
+            }
+            Action::LayerDrill if self.keyboard.has_layers() => {
+                let layout = self.keyboard.layout;
+                self.start_drill(drill::layers(
+                    &[layout.sym, layout.cur],
+                    self.cli.drill_length,
+                ))?
+            }
+            Action::Newline => engine.advance_with('↩'),
+            Action::Indent => engine.indent(),
+            Action::Backspace => engine.backspace(),
+            Action::PreviousWord => engine.move_to(engine.previous_word()),
+            Action::NextWord => engine.move_to(engine.next_word()),
+            Action::LineStart => engine.move_to(engine.line_start()),
+            Action::LineEnd => engine.move_to(engine.line_end()),
+            Action::PreviousSection => engine.move_to(engine.previous_section()),
+            Action::NextSection => engine.move_to(engine.next_section()),
+            Action::ClearHeatmap | Action::LayerDrill | Action::LettersOnly => {}
+        }
+        Ok(())
+    }
+
     fn exit(&mut self) {
         self.exit = true;
     }
@@ -406,217 +1537,249 @@ impl App {
 
 impl Widget for &App {
     fn render(self, block_area: Rect, buf: &mut Buffer) {
-        let title = Line::from(" Story ".bold());
-        let instructions = Line::from(vec![" Exit ".into(), "<Esc> ".blue().bold()]);
-        let block = Block::bordered()
-            .dark_gray()
-            .title(title.centered())
-            .title_bottom(instructions.centered())
-            .border_set(border::ROUNDED);
-        let buff_width = block_area.width as usize / 3;
-        let story = self
-            .file_data
-            .story
-            .chars()
-            .enumerate()
-            .map(|(i, c)| {
-                if i == self.position() {
-                    return c.to_string().white().bold().underlined();
-                }
-                if i > self.position() {
-                    return c.to_string().gray();
-                };
-                if i + buff_width >= self.position() {
-                    return self
-                        .errors
-                        .iter()
-                        .find(|e| e.position == i)
-                        .map(|e| e.char.to_string().red().underlined())
-                        .unwrap_or(c.to_string().dark_gray());
-                }
-                c.to_string().gray()
-            })
-            .skip(self.position().saturating_sub(buff_width));
-        let counter_text = Text::from(vec![Line::from(
-            story.take(2 * buff_width + 1).collect::<Vec<_>>(),
-        )]);
+        let mut title = if self.engine.file_data.freeform {
+            Line::from(" Freeform ".bold())
+        } else if self.engine.file_data.drill {
+            Line::from(" Drill ".bold())
+        } else if self.engine.options.letters_only {
+            Line::from(" Story - Letters Only ".bold())
+        } else {
+            Line::from(" Story ".bold())
+        };
+        // Name the section being typed
+        if let Some(section) = self.engine.file_data.section_at(self.engine.position()) {
+            let (_, name) = &self.engine.file_data.sections[section];
+            title.push_span(format!("- {name} ").bold());
+        }
+        // Color the speed by how accurate this session has been
+        let wpm = match self.engine.session.wpm() {
+            Some(wpm) => {
+                let accuracy = self.engine.session.accuracy();
+                let color = self.config.accuracy.color(accuracy);
+                format!("{wpm:.0} ({accuracy:.0}%) ").fg(color).bold()
+            }
+            None => "-- ".white().bold(),
+        };
+        let current = match self.engine.session.current_wpm() {
+            Some(wpm) => format!("{wpm:.0} "),
+            None => "-- ".to_string(),
+        };
+        let stats = Line::from(vec![
+            " WPM ".into(),
+            wpm,
+            " Now ".into(),
+            current.white().bold(),
+            " Mistakes ".into(),
+            format!("{} ", self.engine.file_data.progress.mistakes)
+                .white()
+                .bold(),
+            " Accuracy ".into(),
+            format!("{:.1}% ", self.engine.file_data.progress.accuracy())
+                .white()
+                .bold(),
+        ]);
+        // Count down the time left in a timed drill
+        let mut timer = match self.engine.remaining() {
+            Some(remaining) => {
+                Line::from(format!(" {} ", format_duration(remaining)).yellow().bold())
+            }
+            None => Line::default(),
+        };
+        // The timer stops while nothing is being typed
+        if self.engine.session.idle() && self.engine.next().is_some() && !self.summary {
+            timer.push_span(" Idle ".yellow().bold());
+        }
+        if let Some(goal) = self.engine.goal() {
+            timer.push_span(" Goal ");
+            timer.push_span(format!("{goal} ").green().bold());
+        }
+        let items = [
+            ("Help", Action::Help),
+            ("Exit", Action::Exit),
+            ("Skip", Action::Skip),
+            ("Pause", Action::Pause),
+            ("Open", Action::Open),
+            ("Letters", Action::LettersOnly),
+            ("Reset", Action::Reset),
+        ];
+        let width = block_area.width.saturating_sub(2);
+        let instructions = instructions(&self.config.keys, &items, width);
+        // Titles overlap rather than make room, so the story's name moves
+        // to the left or goes once the numbers take up the middle
+        let (width, side) = (width as usize, stats.width().max(timer.width()) + 1);
+        let title_fits = title.width() + timer.width() + stats.width() + 2 <= width;
+        let mut block = bordered(self.config.high_contrast);
+        if title.width() + 2 * side <= width {
+            block = block.title(title.centered());
+        } else if title_fits {
+            block = block.title(title.left_aligned());
+        }
+        let block = block
+            .title(timer.left_aligned())
+            .title(stats.right_aligned())
+            .title_bottom(instructions.centered());
         let area = block.inner(block_area);
         block.render(block_area, buf);
-        let [_, area, _] = TuiLayout::vertical([
+        let [story_area, hint_area, gauge_area] = TuiLayout::vertical([
             Constraint::Fill(1),
             Constraint::Length(1),
-            Constraint::Fill(1),
+            Constraint::Length(1),
         ])
         .areas(area);
-        Paragraph::new(counter_text).centered().render(area, buf);
-    }
-}
-
-type Layer = &'static [&'static [char]];
-
-enum Modifier {
-    Shift,
-    Sym,
-    Cur,
-}
-
-struct Location {
-    row: u8,
-    col: u8,
-    modifier: Option<Modifier>,
-}
-
-struct Layout {
-    name: &'static str,
-    base: Layer,
-    sym: Layer,
-    cur: Layer,
-}
-
-impl Layout {
-    fn shift(c: char) -> char {
-        match c {
-            '`' => '~',
-            '1' => '!',
-            '2' => '@',
-            '3' => '#',
-            '4' => '$',
-            '5' => '%',
-            '6' => '^',
-            '7' => '&',
-            '8' => '*',
-            '9' => '(',
-            '0' => ')',
-            '[' => '{',
-            ']' => '}',
-            '\'' => '"',
-            ',' => '<',
-            '.' => '>',
-            '/' => '?',
-            '=' => '+',
-            '\\' => '|',
-            '-' => '_',
-            ';' => ':',
-            c => c.to_ascii_uppercase(),
-        }
-    }
-    fn location(&self, c: char) -> Option<Location> {
-        // Check the base layer
-        for (row_i, row) in self.base.iter().enumerate() {
-            for (col_i, c_candidate) in row.iter().enumerate() {
-                if *c_candidate == c {
-                    return Some(Location {
-                        row: row_i as u8,
-                        col: col_i as u8,
-                        modifier: None,
-                    });
+        // Keep the text to a comfortable reading width
+        let width = self.cli.context.unwrap_or(STORY_WIDTH).max(1);
+        let [story_area] = TuiLayout::horizontal([Constraint::Max(width)])
+            .flex(Flex::Center)
+            .areas(story_area);
+        // The diff view gives every row a second line for what was typed
+        let row_lines = if self.cli.diff { 2 } else { 1 };
+        let (rows, current_row) = self.story_rows(story_area.width, story_area.height / row_lines);
+        let graphemes = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|i| self.engine.file_data.grapheme(*i).unwrap_or_default())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let directions = bidi::paragraph_directions(&graphemes);
+        let word = self.engine.current_word_range();
+        // Keep the current row in the middle of the view
+        let visible = (story_area.height / row_lines) as usize;
+        let middle = visible / 2;
+        let lines = (0..visible)
+            .flat_map(|y| {
+                let Some(row) = (current_row + y)
+                    .checked_sub(middle)
+                    .filter(|row| *row < rows.len())
+                else {
+                    return vec![Line::default(); row_lines as usize];
+                };
+                // Right to left rows are flipped
+                let rtl = directions[row];
+                let order = bidi::visual_order(&graphemes[row], rtl);
+                let story = order
+                    .iter()
+                    .map(|i| self.styled(rows[row][*i], word.as_ref()))
+                    .collect();
+                let mut lines = vec![aligned(story, rtl)];
+                if self.cli.diff {
+                    let typed = order.iter().map(|i| self.typed_span(rows[row][*i]));
+                    lines.push(aligned(typed.collect(), rtl));
                 }
-            }
+                lines
+            })
+            .collect::<Vec<_>>();
+        // Show how far through the story we are, which freeform never is
+        let completion = self.engine.file_data.completion();
+        // Count the characters on the same row, with the rollover once the
+        // story is complete
+        let mut counter = Line::from(
+            format!(
+                " {} / {} characters",
+                self.engine.position(),
+                self.engine.file_data.story_len
+            )
+            .white(),
+        );
+        if self.rollover.supported && self.engine.next().is_none() {
+            counter.push_span(
+                format!(
+                    "  Rollover {:.1}% (avg {}ms)",
+                    self.rollover.percent(),
+                    self.rollover.average().as_millis()
+                )
+                .white(),
+            );
         }
-        // Check the sym layer
-        for (row_i, row) in self.sym.iter().enumerate() {
-            for (col_i, c_candidate) in row.iter().enumerate() {
-                if *c_candidate == c {
-                    return Some(Location {
-                        row: row_i as u8,
-                        col: col_i as u8,
-                        modifier: Some(Modifier::Sym),
-                    });
-                }
-            }
+        let [gauge_area, counter_area] = TuiLayout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Length(counter.width() as u16),
+        ])
+        .areas(gauge_area);
+        counter.render(counter_area, buf);
+        if !self.engine.file_data.freeform {
+            LineGauge::default()
+                .ratio(completion)
+                .label(format!("{:.1}%", completion * 100.0))
+                .filled_style(Style::new().fg(Color::Green))
+                .unfilled_style(Style::new().fg(Color::DarkGray))
+                .render(gauge_area, buf);
         }
-        // Check the cur layer
-        for (row_i, row) in self.cur.iter().enumerate() {
-            for (col_i, c_candidate) in row.iter().enumerate() {
-                if *c_candidate == c {
-                    return Some(Location {
-                        row: row_i as u8,
-                        col: col_i as u8 + 6,
-                        modifier: Some(Modifier::Cur),
-                    });
-                }
-            }
+        Paragraph::new(lines).render(story_area, buf);
+        // Only one message fits under the story, so the most pressing wins
+        let engine = &self.engine;
+        let message =
+            if let (Some(accuracy), Some(target)) = (engine.retry, engine.options.drill_accuracy) {
+                // Say why the line has to be typed again
+                let message =
+                    format!("That line was {accuracy:.0}%, type it again to reach {target:.0}%");
+                Some(message.yellow())
+            } else if let Some(warning) = &engine.file_data.warning
+                && engine.session.correct + engine.session.mistakes == 0
+            {
+                // Warn about problems loading the story until typing starts
+                Some(warning.as_str().yellow())
+            } else if engine.next().is_none() && !engine.file_data.freeform {
+                // Show the results once the story is complete
+                (!self.celebrating()).then(|| " Story complete! ".green().bold())
+            } else if engine.untypeable() {
+                // Point out characters that need skipping
+                let skip = shortcuts::key_for(&self.config.keys, Action::Skip);
+                Some(format!("Not on this layout, skip it with {skip}").dark_gray())
+            } else if let Some(hint) = engine.hint() {
+                // Show the glossary hint for the current word
+                Some(hint.dark_gray().italic())
+            } else {
+                // Spell out the modifier the next character needs
+                engine
+                    .combination()
+                    .map(|combination| combination.dark_gray())
+            };
+        if let Some(message) = message {
+            Paragraph::new(message).centered().render(hint_area, buf);
         }
-        // Check the shifted base layer
-        for (row_i, row) in self.base.iter().enumerate() {
-            for (col_i, c_candidate) in row.iter().enumerate() {
-                if Layout::shift(*c_candidate) == c {
-                    return Some(Location {
-                        row: row_i as u8,
-                        col: col_i as u8,
-                        modifier: Some(Modifier::Shift),
-                    });
-                }
-            }
+        // Check before throwing progress away
+        if self.confirm_reset {
+            let message = Line::from(vec![
+                " Reset all progress on this story? ".yellow().bold(),
+                "<y/n> ".blue().bold(),
+            ]);
+            render_message(message, block_area, buf);
+        }
+        // Cover the story while paused
+        if self.engine.session.paused {
+            let message = Line::from(vec![
+                " PAUSED ".yellow().bold(),
+                " press any key to resume ".gray(),
+            ]);
+            render_message(message, block_area, buf);
         }
-        None
     }
 }
 
-const LAYOUT_QWERTY: Layout = Layout {
-    name: "QWERTY",
-    base: KEYS_QWERTY_BASE,
-    sym: &[],
-    cur: &[],
-};
+// Each pair of fingers gets its own color
+fn finger_theme(finger: Finger) -> &'static Theme {
+    match finger {
+        Finger::LeftPinky | Finger::RightPinky => &THEME_FINGER_PINKY,
+        Finger::LeftRing | Finger::RightRing => &THEME_FINGER_RING,
+        Finger::LeftMiddle | Finger::RightMiddle => &THEME_FINGER_MIDDLE,
+        Finger::LeftIndex | Finger::RightIndex => &THEME_FINGER_INDEX,
+    }
+}
 
-const KEYS_QWERTY_BASE: &[&[char]] = &[
-    &[
-        '`', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '[', ']', '\0',
-    ],
-    &[
-        '\0', 'q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p', '[', ']', '\\',
-    ],
-    &[
-        '\0', 'a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', ';', '\'', '\0', '\0',
-    ],
-    &[
-        '\0', 'z', 'x', 'c', 'v', 'b', 'n', 'm', ',', '.', '/', '\0', '\0', '\0',
-    ],
-];
-
-const LAYOUT_DVORAK: Layout = Layout {
-    name: "Dvorak",
-    base: KEYS_DVORAK_BASE,
-    sym: &[],
-    cur: &[],
-};
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-const KEYS_DVORAK_BASE: &[&[char]] = &[
-    &[
-        '`', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '[', ']', '\0',
-    ],
-    &[
-        '\0', '\'', ',', '.', 'p', 'y', 'f', 'g', 'c', 'r', '/', '=', '\\', '\0',
-    ],
-    &[
-        '\0', 'a', 'o', 'e', 'u', 'i', 'd', 'h', 't', 'n', 's', '-', '\0', '\0',
-    ],
-    &[
-        '\0', ';', 'q', 'j', 'k', 'x', 'b', 'm', 'w', 'v', 'z', '\0', '\0', '\0',
-    ],
-];
-
-const LAYOUT_3L: Layout = Layout {
-    name: "3l",
-    base: KEYS_3L_BASE,
-    sym: KEYS_3L_SYM,
-    cur: KEYS_3L_CUR,
-};
-
-const KEYS_3L_BASE: &[&[char]] = &[
-    &['q', 'f', 'u', 'y', 'z', 'x', 'k', 'c', 'w', 'b'],
-    &['o', 'h', 'e', 'a', 'i', 'd', 'r', 't', 'n', 's'],
-    &[',', 'm', '.', 'j', ';', 'g', 'l', 'p', 'v', '\0'],
-];
-
-const KEYS_3L_SYM: &[&[char]] = &[
-    &['"', '_', '[', ']', '^', '!', '<', '>', '=', '&'],
-    &['/', '-', '{', '}', '*', '?', '(', ')', '\'', ':'],
-    &['#', '$', '|', '~', '`', '+', '%', '\\', '@'],
-];
-const KEYS_3L_CUR: &[&[char]] = &[
-    &['\0', '1', '2', '3'],
-    &['\0', '4', '5', '6'],
-    &['0', '7', '8', '9'],
-];
+    #[test]
+    fn keyboard_renders_into_tiny_areas() {
+        let config = Box::leak(Box::new(Config::default()));
+        let keyboard = Keyboard::from_layout(&LAYOUT_QWERTY, layout::all_layouts(), config);
+        for (width, height) in [(1, 1), (2, 3)] {
+            let area = Rect::new(0, 0, width, height);
+            let mut buf = Buffer::empty(area);
+            keyboard.render(area, &mut buf);
+        }
+    }
+}
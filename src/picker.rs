@@ -0,0 +1,181 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use crate::popup_area;
+use maddi_type::layout::Layout;
+use ratatui::{
+    DefaultTerminal,
+    buffer::Buffer,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::Rect,
+    style::Stylize,
+    symbols::border,
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+
+pub enum Choice {
+    Open(PathBuf),
+    Cancel,
+}
+
+pub enum LayoutChoice {
+    Use(&'static Layout),
+    Cancel,
+}
+
+// Choose a layout by name rather than cycling through them
+pub struct LayoutMenu {
+    pub layouts: &'static [&'static Layout],
+    pub selected: usize,
+}
+
+impl LayoutMenu {
+    pub fn new(layouts: &'static [&'static Layout], current: &Layout) -> Self {
+        let selected = layouts
+            .iter()
+            .position(|layout| std::ptr::eq(*layout, current))
+            .unwrap_or(0);
+        Self { layouts, selected }
+    }
+    pub fn handle_key(&mut self, code: KeyCode) -> Option<LayoutChoice> {
+        match code {
+            KeyCode::Esc => return Some(LayoutChoice::Cancel),
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down if self.selected + 1 < self.layouts.len() => self.selected += 1,
+            KeyCode::Enter => {
+                return self
+                    .layouts
+                    .get(self.selected)
+                    .copied()
+                    .map(LayoutChoice::Use);
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+impl Widget for &LayoutMenu {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = Line::from(" Choose Layout ".bold());
+        let instructions = Line::from(vec![
+            " Use ".into(),
+            "<Enter> ".blue().bold(),
+            " Cancel ".into(),
+            "<Esc> ".blue().bold(),
+        ]);
+        let names = self.layouts.iter().map(|layout| layout.name.to_string());
+        render_list(title, instructions, names, self.selected, area, buf);
+    }
+}
+
+pub struct Picker {
+    pub files: Vec<PathBuf>,
+    pub selected: usize,
+}
+
+impl Picker {
+    pub fn scan(dir: &Path) -> io::Result<Self> {
+        // Find all the text files in the directory
+        let mut files = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "txt") {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(Self { files, selected: 0 })
+    }
+    pub fn handle_key(&mut self, code: KeyCode) -> Option<Choice> {
+        match code {
+            KeyCode::Esc => return Some(Choice::Cancel),
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down if self.selected + 1 < self.files.len() => self.selected += 1,
+            KeyCode::Enter => {
+                return self.files.get(self.selected).cloned().map(Choice::Open);
+            }
+            _ => {}
+        }
+        None
+    }
+    pub fn choose(mut self, terminal: &mut DefaultTerminal) -> io::Result<Option<PathBuf>> {
+        loop {
+            terminal.draw(|frame| frame.render_widget(&self, frame.area()))?;
+            if let Event::Key(key_event) = event::read()?
+                && key_event.kind == KeyEventKind::Press
+            {
+                match self.handle_key(key_event.code) {
+                    Some(Choice::Open(path)) => return Ok(Some(path)),
+                    Some(Choice::Cancel) => return Ok(None),
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
+impl Widget for &Picker {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = Line::from(" Open Story ".bold());
+        let instructions = Line::from(vec![
+            " Open ".into(),
+            "<Enter> ".blue().bold(),
+            " Cancel ".into(),
+            "<Esc> ".blue().bold(),
+        ]);
+        let names = self.files.iter().map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.to_string()
+        });
+        render_list(title, instructions, names, self.selected, area, buf);
+    }
+}
+
+fn render_list(
+    title: Line,
+    instructions: Line,
+    names: impl Iterator<Item = String>,
+    selected: usize,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    let min_width = instructions.width() as u16 + 2;
+    let block = Block::bordered()
+        .dark_gray()
+        .title(title.centered())
+        .title_bottom(instructions.centered())
+        .border_set(border::ROUNDED);
+    // List each name, marking the selected one
+    let mut lines = names
+        .enumerate()
+        .map(|(i, name)| {
+            if i == selected {
+                Line::from(format!("> {name}").white().bold())
+            } else {
+                Line::from(format!("  {name}").gray())
+            }
+        })
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        lines.push(Line::from("No stories found".gray()));
+    }
+    // Center the popup in the available space
+    let width = lines.iter().map(Line::width).max().unwrap_or(0) as u16 + 4;
+    let width = width.max(min_width);
+    let height = lines.len() as u16 + 2;
+    let area = popup_area(area, width, height);
+    // Keep the selection in view when the list overflows
+    let visible = area.height.saturating_sub(2) as usize;
+    let scroll = (selected + 1).saturating_sub(visible);
+    Clear.render(area, buf);
+    Paragraph::new(lines)
+        .scroll((scroll as u16, 0))
+        .block(block)
+        .render(area, buf);
+}
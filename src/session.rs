@@ -0,0 +1,177 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// Gaps between keystrokes longer than this don't count as typing, by default
+const IDLE_LIMIT: Duration = Duration::from_secs(5);
+
+const RECORD_MIN: Duration = Duration::from_secs(10);
+
+// How far back the current speed looks
+const WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+pub struct Session {
+    idle_limit: Option<Duration>,
+    last_keystroke: Option<Instant>,
+    active: Duration,
+    saved_seconds: u64,
+    pub paused: bool,
+    pub correct: usize,
+    pub mistakes: usize,
+    pub skipped: usize,
+    // Keystrokes made by each finger, from left pinky to right pinky
+    pub fingers: [usize; 8],
+    // Misses keyed by the previous and current characters
+    pub pair_misses: HashMap<(char, char), usize>,
+    // Attempts and misses on each keyboard row, from the number row down
+    pub rows: [(usize, usize); 4],
+    // The time since the previous keystroke for each correct character
+    pub latencies: Vec<(char, Duration)>,
+    // When each correct character in the last few seconds was typed
+    recent: VecDeque<Instant>,
+}
+
+impl Session {
+    pub fn new(idle_limit: Option<Duration>) -> Self {
+        Self {
+            idle_limit,
+            ..Self::default()
+        }
+    }
+    fn idle_limit(&self) -> Duration {
+        self.idle_limit.unwrap_or(IDLE_LIMIT)
+    }
+    // Whether the timer has stopped for want of keystrokes
+    pub fn idle(&self) -> bool {
+        self.last_keystroke
+            .is_some_and(|last| last.elapsed() > self.idle_limit())
+    }
+    fn keystroke(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let gap = self
+            .last_keystroke
+            .map(|last| now - last)
+            .filter(|gap| *gap <= self.idle_limit());
+        self.active += gap.unwrap_or_default();
+        self.last_keystroke = Some(now);
+        gap
+    }
+    pub fn record_correct(&mut self, c: char) {
+        if let Some(gap) = self.keystroke() {
+            self.latencies.push((c, gap));
+        }
+        self.correct += 1;
+        self.recent.push_back(Instant::now());
+        while self
+            .recent
+            .front()
+            .is_some_and(|first| first.elapsed() > WINDOW)
+        {
+            self.recent.pop_front();
+        }
+    }
+    pub fn record_mistake(&mut self) {
+        self.keystroke();
+        self.mistakes += 1;
+    }
+    pub fn elapsed(&self) -> Duration {
+        // Include the current gap until it becomes idle
+        let gap = self
+            .last_keystroke
+            .map(|last| last.elapsed())
+            .filter(|gap| *gap <= self.idle_limit())
+            .unwrap_or_default();
+        self.active + gap
+    }
+    pub fn active(&self) -> Duration {
+        // Up to the last keystroke, unlike elapsed
+        self.active
+    }
+    pub fn unsaved_seconds(&self) -> u64 {
+        self.active.as_secs() - self.saved_seconds
+    }
+    pub fn take_unsaved_seconds(&mut self) -> u64 {
+        let seconds = self.active.as_secs();
+        let unsaved = seconds - self.saved_seconds;
+        self.saved_seconds = seconds;
+        unsaved
+    }
+    pub fn pause(&mut self) {
+        self.last_keystroke = None;
+        self.paused = true;
+    }
+    pub fn resume(&mut self) {
+        // The timer restarts from the next keystroke
+        self.paused = false;
+    }
+    pub fn wpm(&self) -> Option<f64> {
+        let minutes = self.elapsed().as_secs_f64() / 60.0;
+        if minutes <= 0.0 {
+            return None;
+        }
+        Some(self.correct as f64 / 5.0 / minutes)
+    }
+    // The speed over just the last few seconds
+    pub fn current_wpm(&self) -> Option<f64> {
+        let recent = self
+            .recent
+            .iter()
+            .filter(|at| at.elapsed() <= WINDOW)
+            .count();
+        let minutes = WINDOW.min(self.elapsed()).as_secs_f64() / 60.0;
+        if recent == 0 || minutes <= 0.0 {
+            return None;
+        }
+        Some(recent as f64 / 5.0 / minutes)
+    }
+    pub fn worst_pairs(&self, count: usize) -> Vec<((char, char), usize)> {
+        let mut pairs = self
+            .pair_misses
+            .iter()
+            .map(|(pair, misses)| (*pair, *misses))
+            .collect::<Vec<_>>();
+        // Most missed first, with ties in a stable order
+        pairs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        pairs.truncate(count);
+        pairs
+    }
+    pub fn slowest(&self, count: usize) -> Vec<(char, Duration)> {
+        let mut totals = HashMap::<char, (Duration, u32)>::new();
+        for (c, latency) in &self.latencies {
+            let (total, n) = totals.entry(*c).or_default();
+            *total += *latency;
+            *n += 1;
+        }
+        let mut slowest = totals
+            .into_iter()
+            .map(|(c, (total, n))| (c, total / n))
+            .collect::<Vec<_>>();
+        // Slowest first, with ties in a stable order
+        slowest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        slowest.truncate(count);
+        slowest
+    }
+    pub fn record_wpm(&self) -> Option<f64> {
+        // Short bursts give wild speeds so they can't set records
+        if self.elapsed() < RECORD_MIN {
+            return None;
+        }
+        self.wpm()
+    }
+    pub fn accuracy(&self) -> f64 {
+        let attempts = self.correct + self.mistakes;
+        if attempts == 0 {
+            return 100.0;
+        }
+        self.correct as f64 / attempts as f64 * 100.0
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
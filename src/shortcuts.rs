@@ -0,0 +1,126 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::config::{Binding, Keys};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Exit,
+    NextLayout,
+    ToggleHints,
+    Help,
+    Reset,
+    Skip,
+    Pause,
+    Open,
+    LettersOnly,
+    Fingers,
+    Reference,
+    Symbols,
+    Heatmap,
+    ClearHeatmap,
+    Analysis,
+    Layouts,
+    LayerDrill,
+    Newline,
+    Indent,
+    Backspace,
+    PreviousWord,
+    NextWord,
+    LineStart,
+    LineEnd,
+    PreviousSection,
+    NextSection,
+}
+
+#[derive(Clone, Copy)]
+pub struct Shortcut {
+    pub binding: Binding,
+    pub action: Action,
+    pub description: &'static str,
+}
+
+const fn shortcut(binding: Binding, action: Action, description: &'static str) -> Shortcut {
+    Shortcut {
+        binding,
+        action,
+        description,
+    }
+}
+
+const fn key(code: KeyCode) -> Binding {
+    Binding::new(code, KeyModifiers::NONE)
+}
+
+// Every fixed binding, in the order the help lists them
+const SHORTCUTS: &[Shortcut] = &[
+    shortcut(key(KeyCode::F(1)), Action::Help, "Show this help"),
+    shortcut(Binding::control('x'), Action::Reset, "Reset progress"),
+    shortcut(Binding::control('s'), Action::Skip, "Skip a character"),
+    shortcut(Binding::control('p'), Action::Pause, "Pause"),
+    shortcut(Binding::control('o'), Action::Open, "Open a story"),
+    shortcut(Binding::control('l'), Action::LettersOnly, "Letters only"),
+    shortcut(Binding::control('f'), Action::Fingers, "Color by finger"),
+    shortcut(Binding::control('r'), Action::Reference, "Layer reference"),
+    shortcut(Binding::control('k'), Action::Symbols, "Shifted symbols"),
+    shortcut(Binding::control('e'), Action::Heatmap, "Miss heatmap"),
+    shortcut(
+        Binding::control('d'),
+        Action::ClearHeatmap,
+        "Clear the heatmap",
+    ),
+    shortcut(Binding::control('a'), Action::Analysis, "Bigram analysis"),
+    shortcut(Binding::control('b'), Action::Layouts, "Choose a layout"),
+    shortcut(Binding::control('g'), Action::LayerDrill, "Layer drill"),
+    shortcut(key(KeyCode::Enter), Action::Newline, "Type a line break"),
+    shortcut(key(KeyCode::Tab), Action::Indent, "Type an indent"),
+    shortcut(key(KeyCode::Backspace), Action::Backspace, "Go back"),
+    shortcut(
+        Binding::new(KeyCode::Left, KeyModifiers::CONTROL),
+        Action::PreviousWord,
+        "Previous word",
+    ),
+    shortcut(
+        Binding::new(KeyCode::Right, KeyModifiers::CONTROL),
+        Action::NextWord,
+        "Next word",
+    ),
+    shortcut(key(KeyCode::Home), Action::LineStart, "Start of line"),
+    shortcut(key(KeyCode::End), Action::LineEnd, "End of line"),
+    shortcut(
+        key(KeyCode::PageUp),
+        Action::PreviousSection,
+        "Previous section",
+    ),
+    shortcut(key(KeyCode::PageDown), Action::NextSection, "Next section"),
+];
+
+// The configurable bindings come first so they win over the fixed ones
+pub fn shortcuts(keys: &Keys) -> impl Iterator<Item = Shortcut> {
+    [
+        shortcut(keys.exit, Action::Exit, "Finish the session"),
+        shortcut(keys.next_layout, Action::NextLayout, "Next layout"),
+        shortcut(
+            keys.toggle_hints,
+            Action::ToggleHints,
+            "Toggle the keyboard",
+        ),
+    ]
+    .into_iter()
+    .chain(SHORTCUTS.iter().copied())
+}
+
+pub fn action(keys: &Keys, key_event: &KeyEvent) -> Option<Action> {
+    shortcuts(keys)
+        .find(|shortcut| shortcut.binding.matches(key_event))
+        .map(|shortcut| shortcut.action)
+}
+
+// The key for an action as the instruction bars show it
+pub fn key_for(keys: &Keys, action: Action) -> String {
+    shortcuts(keys)
+        .find(|shortcut| shortcut.action == action)
+        .map_or_else(String::new, |shortcut| shortcut.binding.to_string())
+}
@@ -0,0 +1,361 @@
+// SPDX-FileCopyrightText: 2025 Madeline Baggins <declanbaggins@gmail.com>
+//
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use crate::shortcuts::{self, Action};
+use crate::{App, popup_area};
+use maddi_type::layout::{Bigrams, Finger, Layout, ROW_NAMES};
+use maddi_type::session::format_duration;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Modifier, Style, Stylize},
+    symbols::border,
+    text::Line,
+    widgets::{Block, Clear, Padding, Paragraph, Widget},
+};
+
+pub struct Summary<'a> {
+    pub app: &'a App,
+}
+
+impl Summary<'_> {
+    fn lines(&self) -> Vec<Line<'static>> {
+        let session = &self.app.engine.session;
+        let wpm = match session.wpm() {
+            Some(wpm) => format!("{wpm:.0}"),
+            None => "--".to_string(),
+        };
+        let row = |label: &str, value: String| {
+            Line::from(vec![format!("{label:<14}").gray(), value.white().bold()]).left_aligned()
+        };
+        let mut lines = vec![
+            row("Characters", session.correct.to_string()),
+            row("Mistakes", session.mistakes.to_string()),
+            Line::from(vec![
+                format!("{:<14}", "Skipped").gray(),
+                session.skipped.to_string().yellow().bold(),
+            ])
+            .left_aligned(),
+            row("Accuracy", format!("{:.1}%", session.accuracy())),
+            row("Time", format_duration(session.elapsed())),
+            row("WPM", wpm),
+        ];
+        // Say how the race against the ghost went as of the last keystroke
+        if let Some(ghost) = self.app.engine.ghost_at(session.active()) {
+            let position = self.app.engine.position();
+            let gap = match position.cmp(&ghost) {
+                Ordering::Greater => format!("{} ahead", position - ghost).green().bold(),
+                Ordering::Less => format!("{} behind", ghost - position).red().bold(),
+                Ordering::Equal => "Level".white().bold(),
+            };
+            lines.push(Line::from(vec![format!("{:<14}", "Ghost").gray(), gap]).left_aligned());
+        }
+        // Compare against the best session so far
+        let best = self.app.engine.file_data.progress.best_wpm;
+        match session.record_wpm() {
+            Some(wpm) if wpm > best => {
+                lines.push(Line::from(" New best! ".green().bold()));
+            }
+            _ if best > 0.0 => lines.push(row("Best", format!("{best:.0} WPM"))),
+            _ => {}
+        }
+        // Break the keystrokes down by hand and finger
+        let total = session.fingers.iter().sum::<usize>();
+        if total > 0 {
+            let share = |count: usize| format!("{:.0}%", count as f64 / total as f64 * 100.0);
+            let left = Finger::ALL
+                .iter()
+                .filter(|finger| finger.is_left())
+                .map(|finger| session.fingers[*finger as usize])
+                .sum::<usize>();
+            // Pair each finger with its mirror on the other hand
+            let both =
+                |left: usize, right: usize| format!("L {:<5} R {}", share(left), share(right));
+            lines.push(Line::default());
+            lines.push(row("Hands", both(left, total - left)));
+            for i in 0..Finger::ALL.len() / 2 {
+                let (left, right) = (Finger::ALL[i], Finger::ALL[Finger::ALL.len() - 1 - i]);
+                let name = left.name().trim_start_matches("Left ");
+                lines.push(row(
+                    name,
+                    both(
+                        session.fingers[left as usize],
+                        session.fingers[right as usize],
+                    ),
+                ));
+            }
+        }
+        // Compare accuracy across the keyboard rows
+        if session.rows.iter().any(|(attempts, _)| *attempts > 0) {
+            lines.push(Line::default());
+            for (name, (attempts, misses)) in ROW_NAMES.iter().zip(session.rows) {
+                let accuracy = match attempts {
+                    0 => "--".to_string(),
+                    _ => format!(
+                        "{:.1}%",
+                        (attempts - misses) as f64 / attempts as f64 * 100.0
+                    ),
+                };
+                lines.push(row(&format!("{name} Row"), accuracy));
+            }
+        }
+        // Show where typing sped up and slowed down
+        if !session.latencies.is_empty() {
+            lines.push(Line::default());
+            lines.push(
+                Line::from(vec![
+                    format!("{:<14}", "Rhythm").gray(),
+                    sparkline(&session.latencies).cyan(),
+                ])
+                .left_aligned(),
+            );
+            for (c, latency) in session.slowest(3) {
+                let visible = if c == ' ' { '␣' } else { c };
+                let label = format!("Slowest {visible}");
+                lines.push(row(&label, format!("{}ms", latency.as_millis())));
+            }
+        }
+        // List the pairs of characters that went wrong most
+        let pairs = session.worst_pairs(3);
+        if !pairs.is_empty() {
+            lines.push(Line::default());
+            for ((previous, current), misses) in pairs {
+                let visible = |c: char| if c == ' ' { '␣' } else { c };
+                let pair = format!("Missed {}{}", visible(previous), visible(current));
+                lines.push(row(&pair, misses.to_string()));
+            }
+        }
+        lines
+    }
+}
+
+impl Widget for Summary<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = if self.app.engine.time_up() {
+            Line::from(" Time's Up ".bold())
+        } else if self.app.engine.goal_met() {
+            Line::from(" Goal Met ".bold())
+        } else {
+            Line::from(" Session Summary ".bold())
+        };
+        let instructions = Line::from(vec![
+            " Drill ".into(),
+            "<d> ".blue().bold(),
+            " Exit ".into(),
+            "<Any Key> ".blue().bold(),
+        ]);
+        let lines = self.lines();
+        render_scrolling_popup(
+            title,
+            instructions,
+            lines,
+            Some(&self.app.scroll),
+            area,
+            buf,
+        );
+    }
+}
+
+pub struct Help<'a> {
+    pub app: &'a App,
+}
+
+impl Widget for Help<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // Dim everything behind the list
+        buf.set_style(area, Style::new().add_modifier(Modifier::DIM));
+        let entry = |binding: String, description: &str| {
+            Line::from(vec![
+                format!("{binding:<12}").blue().bold(),
+                format!("{description:<20}").white(),
+            ])
+        };
+        let mut lines = Vec::new();
+        for shortcut in shortcuts::shortcuts(&self.app.config.keys) {
+            lines.push(entry(shortcut.binding.to_string(), shortcut.description));
+            if shortcut.action == Action::Help && self.app.question_mark_helps() {
+                lines.push(entry("<?>".to_string(), shortcut.description));
+            }
+        }
+        let title = Line::from(" Keybindings ".bold());
+        let instructions = Line::from(vec![" Close ".into(), "<Any Key> ".blue().bold()]);
+        render_scrolling_popup(
+            title,
+            instructions,
+            lines,
+            Some(&self.app.scroll),
+            area,
+            buf,
+        );
+    }
+}
+
+// How many bars the rhythm graph is squeezed into
+const SPARKLINE_WIDTH: usize = 30;
+
+fn sparkline(latencies: &[(char, Duration)]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    // Average consecutive keystrokes together to fit the width
+    let size = latencies.len().div_ceil(SPARKLINE_WIDTH);
+    let averages = latencies
+        .chunks(size)
+        .map(|chunk| {
+            chunk.iter().map(|(_, latency)| *latency).sum::<Duration>() / chunk.len() as u32
+        })
+        .collect::<Vec<_>>();
+    let slowest = averages.iter().max().copied().unwrap_or_default();
+    averages
+        .iter()
+        .map(|average| {
+            let height = average.as_secs_f64() / slowest.as_secs_f64().max(f64::EPSILON);
+            BARS[((height * (BARS.len() - 1) as f64).round() as usize).min(BARS.len() - 1)]
+        })
+        .collect()
+}
+
+pub struct Completed<'a> {
+    pub app: &'a App,
+}
+
+impl Widget for Completed<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let app = self.app;
+        let progress = &app.engine.file_data.progress;
+        let seconds = progress.seconds + app.engine.session.unsaved_seconds();
+        let row = |label: &str, value: String| {
+            Line::from(vec![format!("{label:<14}").gray(), value.white().bold()]).left_aligned()
+        };
+        let lines = vec![
+            Line::from("Congratulations!".green().bold()),
+            Line::default(),
+            row("Characters", app.engine.file_data.story_len.to_string()),
+            row("Mistakes", progress.mistakes.to_string()),
+            row("Skipped", progress.skipped.to_string()),
+            row("Accuracy", format!("{:.1}%", progress.accuracy())),
+            row("Time", format_duration(Duration::from_secs(seconds))),
+        ];
+        let title = Line::from(" Story Complete ".bold());
+        let instructions = Line::from(vec![
+            " Start Over ".into(),
+            "<r> ".blue().bold(),
+            " Exit ".into(),
+            format!("{} ", app.config.keys.exit).blue().bold(),
+            " Close ".into(),
+            "<Any Key> ".blue().bold(),
+        ]);
+        render_popup(title, instructions, lines, area, buf);
+    }
+}
+
+pub struct Analysis<'a> {
+    pub app: &'a App,
+    pub bigrams: &'a [(&'static Layout, Bigrams)],
+}
+
+impl Widget for Analysis<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut lines = vec![
+            Line::from(format!("{:<18}{:>10}{:>10}", "Layout", "SFB", "Alternate").gray()),
+            Line::default(),
+        ];
+        for (layout, bigrams) in self.bigrams {
+            let line = format!(
+                "{:<18}{:>9.1}%{:>9.1}%",
+                layout.name,
+                bigrams.same_finger_percent(),
+                bigrams.alternating_percent()
+            );
+            lines.push(match std::ptr::eq(*layout, self.app.keyboard.layout) {
+                true => Line::from(line.green().bold()),
+                false => Line::from(line.white()),
+            });
+        }
+        let title = Line::from(" Bigram Analysis ".bold());
+        let instructions = Line::from(vec![" Close ".into(), "<Any Key> ".blue().bold()]);
+        render_popup(title, instructions, lines, area, buf);
+    }
+}
+
+// How many of the worst keys the report lists
+const REPORT_KEYS: usize = 8;
+
+pub struct Report<'a> {
+    pub app: &'a App,
+}
+
+impl Widget for Report<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut worst = self
+            .app
+            .engine
+            .file_data
+            .progress
+            .misses
+            .iter()
+            .collect::<Vec<_>>();
+        worst.sort_by(|a, b| b.1.cmp(a.1));
+        let mut lines = vec![
+            Line::from(format!("{:<10}{:>10}", "Key", "Misses").gray()),
+            Line::default(),
+        ];
+        for (c, misses) in worst.into_iter().take(REPORT_KEYS) {
+            let key = match c {
+                ' ' => "space".to_string(),
+                c => c.to_string(),
+            };
+            lines.push(Line::from(format!("{key:<10}{misses:>10}").white()));
+        }
+        let title = Line::from(" Last Time ".bold());
+        let instructions = Line::from(vec![" Start ".into(), "<Any Key> ".blue().bold()]);
+        render_popup(title, instructions, lines, area, buf);
+    }
+}
+
+fn render_popup(title: Line, instructions: Line, lines: Vec<Line>, area: Rect, buf: &mut Buffer) {
+    render_scrolling_popup(title, instructions, lines, None, area, buf);
+}
+
+// Popups taller than the screen show a window onto their lines instead
+fn render_scrolling_popup(
+    title: Line,
+    mut instructions: Line,
+    lines: Vec<Line>,
+    scroll: Option<&Cell<u16>>,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    let overflow = (lines.len() as u16 + 2).saturating_sub(area.height);
+    if let Some(scroll) = scroll {
+        scroll.set(scroll.get().min(overflow));
+        if overflow > 0 {
+            instructions.push_span(" Scroll ");
+            instructions.push_span("<↑↓> ".blue().bold());
+        }
+    }
+    let width = lines
+        .iter()
+        .map(Line::width)
+        .chain([title.width(), instructions.width()])
+        .max()
+        .unwrap_or(0) as u16
+        + 4;
+    let height = lines.len() as u16 + 2 - overflow;
+    let block = Block::bordered()
+        .dark_gray()
+        .title(title.centered())
+        .title_bottom(instructions.centered())
+        .border_set(border::ROUNDED)
+        .padding(Padding::horizontal(1));
+    let area = popup_area(area, width, height);
+    Clear.render(area, buf);
+    Paragraph::new(lines)
+        .centered()
+        .scroll((scroll.map_or(0, Cell::get), 0))
+        .block(block)
+        .render(area, buf);
+}